@@ -0,0 +1,77 @@
+// Each value passed to `show` is checked against the comments on its line: `// domain: value`
+// is what `domain` must know about it (see `src/main.rs`).
+fn show<T>(_: T) {}
+
+fn wrapping(x: u8) {
+    let a = x % 4 + 252;
+    show(a); // interval: Range(252, 255)
+    let b = a + 8;
+    show(b); // interval: Range(4, 7)
+    let c: u8 = 200;
+    let d = c + 100;
+    show(d); // flat: Value(44)
+    let e = x * 6 + 3;
+    show(e); // congruence: Mod { modulus: 2, residue: 1 }
+}
+
+fn shifts(x: u32) {
+    let a = x % 4 << 3;
+    show(a); // interval: Range(0, 24)
+    let b = (x | 1) << 2;
+    show(b); // known_bits: Bits { value: 4, mask: 4294967288 }
+    let c = x >> 28;
+    show(c); // interval: Range(0, 15)
+}
+
+fn remainders(x: i32, y: u32) {
+    let a = x % 8;
+    show(a); // interval: Range(-7, 7)
+    if y % 4 == 1 {
+        show(y); // congruence: Mod { modulus: 4, residue: 1 }
+        if y >= 2 {
+            if y < 8 {
+                show(y); // product: (Range(5, 5), Mod { modulus: 0, residue: 5 })
+            }
+        }
+    }
+}
+
+fn octagon(n: i32) {
+    let mut i = 0;
+    let mut j = 0;
+    while i < n {
+        i += 1;
+        j += 1;
+    }
+    if i >= 5 {
+        show(j); // octagon: Some((5, 2147483647))
+    }
+}
+
+fn linear_equalities(n: i32) {
+    let mut i = 0;
+    let mut k = 1;
+    while i < n {
+        i += 1;
+        k += 2;
+    }
+    if i == 3 {
+        show(k); // linear_equalities: Some(7)
+    }
+}
+
+// Like `test_fn5` in `examples/precise_sign_analysis`, but the product is taken after the paths
+// where `x` and `y` have the same sign join
+fn test_fn5(x: i32, y: i32) {
+    let arr = [1, 2, 3, 4];
+    if x <= 0 {
+        if y > 0 {
+            return;
+        }
+    } else if y <= 0 {
+        return;
+    }
+    let idx = x * y;
+    show(idx); // sign: Top // disjunctive: GreaterEqual
+    println!("{}", arr[idx as usize]);
+}
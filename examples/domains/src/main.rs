@@ -0,0 +1,144 @@
+#![feature(rustc_private)]
+extern crate dataflow;
+
+use dataflow::congruence::Congruence;
+use dataflow::disjunctive::{Disjunctive, FourPaths};
+use dataflow::flat::ConstantPropagation;
+use dataflow::interval::Interval;
+use dataflow::known_bits::KnownBits;
+use dataflow::lattice::{LocalMap, Overflow, PreciseSignAnalysis, SimpleLattice};
+use dataflow::linear_equalities::LinearEqualities;
+use dataflow::mir::{Local, Operand, Place, PlaceBase, Rvalue, Statement, StatementKind};
+use dataflow::octagon::Octagon;
+use dataflow::product::{IntervalCongruence, Product};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The analysed file, whose comments say what each domain must find
+const SOURCE: &str = include_str!("../example.rs");
+
+static CHECKED: AtomicUsize = AtomicUsize::new(0);
+static FAILED: AtomicUsize = AtomicUsize::new(0);
+
+/// What `domain` must know about the value passed to `show` on `line`, if anything
+fn expected(domain: &str, line: usize) -> Option<&'static str> {
+    let source = SOURCE.lines().nth(line - 1)?;
+    source.split("// ").skip(1).find_map(|comment| {
+        let mut parts = comment.splitn(2, ": ");
+        match (parts.next(), parts.next()) {
+            (Some(name), Some(value)) if name == domain => Some(value.trim()),
+            _ => None,
+        }
+    })
+}
+
+/// Compares what `domain` knows about the local copied by `stmt` with its comment, if there is
+/// one. `describe` formats the value of a local in the state before `stmt`.
+fn check<D: Fn(Local) -> String>(domain: &str, stmt: &Statement, describe: D) {
+    let local = match stmt.kind {
+        StatementKind::Assign(_, ref rvalue) => match **rvalue {
+            Rvalue::Use(Operand::Copy(Place::Base(PlaceBase::Local(local))))
+            | Rvalue::Use(Operand::Move(Place::Base(PlaceBase::Local(local)))) => Some(local),
+            _ => None,
+        },
+        _ => None,
+    };
+    // Spans are printed as `file:line:column: line:column`
+    let span = format!("{:?}", stmt.source_info.span);
+    let line = span.split(':').nth(1).and_then(|line| line.parse().ok());
+    let (local, line, expected) = match (local, line) {
+        (Some(local), Some(line)) => match expected(domain, line) {
+            Some(expected) => (local, line, expected),
+            None => return,
+        },
+        _ => return,
+    };
+    let actual = describe(local);
+    CHECKED.fetch_add(1, Ordering::SeqCst);
+    if actual == expected {
+        println!("ok: {} at line {} knows {}", domain, line, actual);
+    } else {
+        FAILED.fetch_add(1, Ordering::SeqCst);
+        println!(
+            "FAILED: {} at line {} knows {} instead of {}",
+            domain, line, actual, expected
+        );
+    }
+}
+
+fn interval(stmt: &Statement, state: &LocalMap<Interval>) {
+    check("interval", stmt, |local| format!("{:?}", state[&local]));
+}
+
+fn flat(stmt: &Statement, state: &LocalMap<ConstantPropagation>) {
+    check("flat", stmt, |local| format!("{:?}", state[&local]));
+}
+
+fn congruence(stmt: &Statement, state: &LocalMap<Congruence>) {
+    check("congruence", stmt, |local| format!("{:?}", state[&local]));
+}
+
+fn known_bits(stmt: &Statement, state: &LocalMap<KnownBits>) {
+    check("known_bits", stmt, |local| format!("{:?}", state[&local]));
+}
+
+fn product(stmt: &Statement, state: &LocalMap<Product<Interval, Congruence, IntervalCongruence>>) {
+    check("product", stmt, |local| format!("{:?}", state[&local]));
+}
+
+fn octagon(stmt: &Statement, state: &Octagon) {
+    check("octagon", stmt, |local| {
+        format!("{:?}", state.interval(local))
+    });
+}
+
+fn linear_equalities(stmt: &Statement, state: &LinearEqualities) {
+    check("linear_equalities", stmt, |local| {
+        format!("{:?}", state.constant(local))
+    });
+}
+
+fn sign(stmt: &Statement, state: &LocalMap<PreciseSignAnalysis>) {
+    check("sign", stmt, |local| format!("{:?}", state[&local]));
+}
+
+fn disjunctive(stmt: &Statement, state: &Disjunctive<LocalMap<PreciseSignAnalysis>, FourPaths>) {
+    check("disjunctive", stmt, |local| {
+        let value = state
+            .disjuncts()
+            .fold(PreciseSignAnalysis::bot(), |value, disjunct| {
+                SimpleLattice::join(&value, &disjunct[&local])
+            });
+        format!("{:?}", value)
+    });
+}
+
+fn main() {
+    let target = "example.rs";
+    // Wrapping arithmetic, as when the code is built with `-O`
+    let wrapping = dataflow::Config::default;
+    // The sign and linear equality lattices assume arithmetic doesn't wrap around
+    let checked = || dataflow::Config {
+        overflow: Overflow::Checked,
+        ..Default::default()
+    };
+    dataflow::run_with_config(target, &interval, wrapping());
+    dataflow::run_with_config(target, &flat, wrapping());
+    dataflow::run_with_config(target, &congruence, wrapping());
+    dataflow::run_with_config(target, &known_bits, wrapping());
+    dataflow::run_with_config(target, &product, wrapping());
+    dataflow::run_with_config(target, &octagon, wrapping());
+    dataflow::run_with_config(target, &linear_equalities, checked());
+    dataflow::run_with_config(target, &sign, checked());
+    dataflow::run_with_config(target, &disjunctive, checked());
+
+    // Every comment must have been checked, so that a value that is never reached doesn't pass
+    let expected = SOURCE
+        .lines()
+        .filter(|line| line.trim_start().starts_with("show("))
+        .map(|line| line.matches("// ").count())
+        .sum::<usize>();
+    let checked = CHECKED.load(Ordering::SeqCst);
+    let failed = FAILED.load(Ordering::SeqCst);
+    println!("{} of {} checks done, {} failed", checked, expected, failed);
+    assert!(checked == expected && failed == 0);
+}
@@ -0,0 +1,51 @@
+#![feature(rustc_private)]
+extern crate dataflow;
+
+use dataflow::lattice::PreciseSignAnalysis;
+use dataflow::mir::{Local, Statement};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::time::Instant;
+
+// Nothing is checked, only the time the analysis takes is of interest. The lattice and the
+// settings only use what existed before transfer functions worked in place, so that both versions
+// can be compared.
+fn f(_stmt: &Statement, _input: &HashMap<Local, PreciseSignAnalysis>) {}
+
+// A function with `n` locals that stay live until the end and `n` branches, so that both the
+// number of blocks and the size of the states grow with `n`.
+fn generate(n: usize) -> String {
+    let mut source = String::from("fn main() {\n    large(0, 0);\n}\n\n");
+    source.push_str("fn large(x: i64, mut y: i64) -> i64 {\n");
+    for i in 0..n {
+        source.push_str(&format!("    let v{} = x % {} + y;\n", i, i + 2));
+        source.push_str(&format!(
+            "    if v{} > {} {{ y = v{} - 1; }} else {{ y = v{} + 1; }}\n",
+            i, i, i, i
+        ));
+    }
+    source.push_str("    let mut sum = 0;\n");
+    for i in 0..n {
+        source.push_str(&format!("    sum = sum + v{};\n", i));
+    }
+    source.push_str("    sum + y\n}\n");
+    source
+}
+
+fn main() {
+    let n = env::args()
+        .nth(1)
+        .map(|arg| arg.parse().expect("the argument is the number of branches"))
+        .unwrap_or(1000);
+    fs::write("generated.rs", generate(n)).expect("could not write generated.rs");
+
+    let config = dataflow::Config {
+        print_metrics: true,
+        ..Default::default()
+    };
+    let started = Instant::now();
+    dataflow::run_with_config("generated.rs", &f, config);
+    // This includes compiling the generated code up to MIR, which doesn't depend on the lattice.
+    println!("Analysed {} branches in {:?}", n, started.elapsed());
+}
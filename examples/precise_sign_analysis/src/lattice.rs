@@ -1,5 +1,8 @@
+use dataflow::int::ConstInt;
+use dataflow::lattice::Arith;
 use dataflow::mir::interpret::ConstValue;
 use dataflow::mir::{BinOp, UnOp};
+use dataflow::ty::layout::Size;
 use dataflow::ty::TyKind;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -55,23 +58,17 @@ impl dataflow::lattice::SimpleLattice for PreciseSign {
         }
     }
 
-    fn alpha(a: ConstValue) -> Self {
-        match a {
-            ConstValue::Scalar(scalar) => {
-                let x = scalar.to_i32().unwrap();
-                if x < 0 {
-                    Lower
-                } else if x > 0 {
-                    Greater
-                } else {
-                    Zero
-                }
-            }
-            _ => Top,
+    fn alpha(ty: &TyKind, size: Size, a: ConstValue) -> Self {
+        match ConstInt::decode(ty, size, a) {
+            Some(ConstInt::Signed(x)) if x < 0 => Lower,
+            Some(ConstInt::Signed(x)) if x > 0 => Greater,
+            Some(ConstInt::Unsigned(x)) if x > 0 => Greater,
+            Some(_) => Zero,
+            None => Top,
         }
     }
 
-    fn flow_binop(op: &BinOp, arg1: &Self, arg2: &Self) -> Self {
+    fn flow_binop(op: &BinOp, arg1: &Self, arg2: &Self, _arith: &Arith) -> Self {
         match op {
             BinOp::Add => match (arg1, arg2) {
                 (Greater, Zero)
@@ -132,7 +129,7 @@ impl dataflow::lattice::SimpleLattice for PreciseSign {
         }
     }
 
-    fn flow_unop(op: &UnOp, arg: &Self) -> Self {
+    fn flow_unop(op: &UnOp, arg: &Self, _arith: &Arith) -> Self {
         match op {
             UnOp::Neg => match arg {
                 Zero => Zero,
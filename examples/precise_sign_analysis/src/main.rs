@@ -4,12 +4,12 @@ extern crate dataflow;
 mod lattice;
 mod lattice2;
 
-use dataflow::mir::{CastKind, Local, Operand, Place, PlaceBase, Rvalue, Statement, StatementKind};
+use dataflow::mir::{CastKind, Operand, Place, PlaceBase, Rvalue, Statement, StatementKind};
+use dataflow::lattice::{LocalMap, LocalStore, Overflow};
 use dataflow::ty::TyKind;
-use std::collections::HashMap;
 
 // f receives a statement `stmt` and the dataflow information associated with that statement `input`.
-fn f(stmt: &Statement, input: &HashMap<Local, lattice::PreciseSign>){
+fn f(stmt: &Statement, input: &LocalMap<lattice::PreciseSign>){
     if let StatementKind::Assign(_, ref rvalue) = stmt.kind {
         match &**rvalue {
             Rvalue::Cast(CastKind::Misc, op1, ty) => match ty.sty {
@@ -42,5 +42,11 @@ fn f(stmt: &Statement, input: &HashMap<Local, lattice::PreciseSign>){
 }
 
 fn main() {
-    dataflow::run("example.rs", &f);
+    // The lattices in this example assume arithmetic never wraps around, so we analyse the code as
+    // if it was compiled with overflow checks.
+    let config = dataflow::Config {
+        overflow: Overflow::Checked,
+        ..Default::default()
+    };
+    dataflow::run_with_config("example.rs", &f, config);
 }
@@ -1,33 +1,36 @@
-use rustc::mir::{BasicBlock, BasicBlockData};
+use rustc::mir::BasicBlock;
 
-pub struct Block<'tcx> {
+pub struct Block {
     pub id: BasicBlock,
-    pub data: &'tcx BasicBlockData<'tcx>,
     pub idx: usize,
 }
 
-impl<'tcx> PartialEq for Block<'tcx> {
+impl PartialEq for Block {
     fn eq(&self, other: &Self) -> bool {
-        self.id == other.id
+        self.idx == other.idx && self.id == other.id
     }
 }
 
-impl<'tcx> Eq for Block<'tcx> {}
+impl Eq for Block {}
 
-impl<'tcx> Ord for Block<'tcx> {
+impl Ord for Block {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        other.id.cmp(&self.id) // Note that the order is reversed (so we can use std::collections::BinaryHeap as a min-heap)
+        // Note that the order is reversed (so we can use std::collections::BinaryHeap as a min-heap)
+        other
+            .idx
+            .cmp(&self.idx)
+            .then_with(|| other.id.cmp(&self.id))
     }
 }
 
-impl<'tcx> PartialOrd for Block<'tcx> {
+impl PartialOrd for Block {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
 
-impl<'tcx> Block<'tcx> {
-    pub fn new(id: BasicBlock, data: &'tcx BasicBlockData<'tcx>, idx: usize) -> Self {
-        Self { id, data, idx }
+impl Block {
+    pub fn new(id: BasicBlock, idx: usize) -> Self {
+        Self { id, idx }
     }
 }
@@ -0,0 +1,149 @@
+use crate::dominators::DominatorTree;
+use crate::loops::Loops;
+use rustc::mir::{BasicBlock, BasicBlockData, Successors, START_BLOCK};
+use rustc_data_structures::indexed_vec::IndexVec;
+use std::collections::HashMap;
+
+/// Control flow information about a function
+#[derive(Debug, Clone)]
+pub struct ControlFlow {
+    pub dominators: DominatorTree,
+    pub post_dominators: DominatorTree,
+    pub loops: Loops,
+}
+
+impl ControlFlow {
+    pub fn new(blocks: &IndexVec<BasicBlock, BasicBlockData>) -> Self {
+        let dominators = DominatorTree::dominators(blocks);
+        let loops = Loops::new(blocks, &dominators);
+        ControlFlow {
+            dominators,
+            post_dominators: DominatorTree::post_dominators(blocks),
+            loops,
+        }
+    }
+}
+
+/// Blocks reachable from the start block, in reverse postorder. The traversal uses an explicit
+/// stack, so it works on functions of any size. Unreachable blocks are not included.
+pub fn reverse_postorder(blocks: &IndexVec<BasicBlock, BasicBlockData>) -> Vec<BasicBlock> {
+    let mut postorder = Vec::with_capacity(blocks.len());
+    if blocks.is_empty() {
+        return postorder;
+    }
+    let mut visited = IndexVec::from_elem_n(false, blocks.len());
+    // Each entry is a block and its successors left to visit
+    let mut stack = vec![(START_BLOCK, blocks[START_BLOCK].terminator().successors())];
+    visited[START_BLOCK] = true;
+    while let Some((block, successors)) = stack.last_mut() {
+        match successors.next() {
+            Some(&suc) => {
+                if !visited[suc] {
+                    visited[suc] = true;
+                    stack.push((suc, blocks[suc].terminator().successors()));
+                }
+            }
+            None => {
+                postorder.push(*block);
+                stack.pop();
+            }
+        }
+    }
+    postorder.reverse();
+    postorder
+}
+
+/// Reverse postorder index of each reachable block. Unreachable blocks have no entry.
+pub fn reverse_postorder_indices(
+    blocks: &IndexVec<BasicBlock, BasicBlockData>,
+) -> HashMap<BasicBlock, usize> {
+    reverse_postorder(blocks)
+        .into_iter()
+        .enumerate()
+        .map(|(i, block)| (block, i))
+        .collect()
+}
+
+/// Predecessors of each block, in block order
+pub fn predecessors(
+    blocks: &IndexVec<BasicBlock, BasicBlockData>,
+) -> IndexVec<BasicBlock, Vec<BasicBlock>> {
+    let mut preds = IndexVec::from_elem_n(Vec::new(), blocks.len());
+    for (block, data) in blocks.iter_enumerated() {
+        for &suc in data.terminator().successors() {
+            preds[suc].push(block);
+        }
+    }
+    preds
+}
+
+/// Index of the strongly connected component of each block of the subgraph induced by `include`,
+/// using an iterative version of Tarjan's algorithm. Components are numbered in reverse topological
+/// order. Blocks outside the subgraph don't get a component.
+pub fn strongly_connected_components(
+    blocks: &IndexVec<BasicBlock, BasicBlockData>,
+    include: &IndexVec<BasicBlock, bool>,
+) -> IndexVec<BasicBlock, Option<usize>> {
+    let n = blocks.len();
+    let mut index: IndexVec<BasicBlock, Option<usize>> = IndexVec::from_elem_n(None, n);
+    let mut low = IndexVec::from_elem_n(0, n);
+    let mut on_stack = IndexVec::from_elem_n(false, n);
+    let mut component = IndexVec::from_elem_n(None, n);
+    let mut stack = Vec::new();
+    // Each entry is a block and its successors left to visit
+    let mut call_stack: Vec<(BasicBlock, Successors)> = Vec::new();
+    let mut next_index = 0;
+    let mut next_component = 0;
+
+    for root in blocks.indices() {
+        if !include[root] || index[root].is_some() {
+            continue;
+        }
+        index[root] = Some(next_index);
+        low[root] = next_index;
+        next_index += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        call_stack.push((root, blocks[root].terminator().successors()));
+
+        while let Some((v, successors)) = call_stack.last_mut() {
+            let v = *v;
+            let next = successors.next().cloned();
+            match next.filter(|&w| include[w]) {
+                Some(w) => match index[w] {
+                    None => {
+                        index[w] = Some(next_index);
+                        low[w] = next_index;
+                        next_index += 1;
+                        stack.push(w);
+                        on_stack[w] = true;
+                        call_stack.push((w, blocks[w].terminator().successors()));
+                    }
+                    Some(w_index) => {
+                        if on_stack[w] {
+                            low[v] = low[v].min(w_index);
+                        }
+                    }
+                },
+                None if next.is_some() => {} // Edge leaving the subgraph
+                None => {
+                    call_stack.pop();
+                    if let Some(&(parent, _)) = call_stack.last() {
+                        low[parent] = low[parent].min(low[v]);
+                    }
+                    if Some(low[v]) == index[v] {
+                        while let Some(w) = stack.pop() {
+                            on_stack[w] = false;
+                            component[w] = Some(next_component);
+                            if w == v {
+                                break;
+                            }
+                        }
+                        next_component += 1;
+                    }
+                }
+            }
+        }
+    }
+    component
+}
@@ -0,0 +1,69 @@
+use crate::lattice::Overflow;
+use crate::worklist::Strategy;
+use std::fmt;
+use std::time::Duration;
+
+/// Settings of an analysis run
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Overflow semantics of the analysed code. This also decides the flags it is compiled with.
+    pub overflow: Overflow,
+    /// Order in which blocks are processed
+    pub strategy: Strategy,
+    /// Print worklist metrics after analysing each function
+    pub print_metrics: bool,
+    /// Only keep the states of join points and loop heads. The other states are recomputed from
+    /// their predecessor, and checkers then see the reachable blocks in reverse postorder.
+    pub sparse: bool,
+    /// Number of threads functions are analysed on. Checkers always run on the calling thread,
+    /// one function after another in the usual order. Transfer functions run on the worker
+    /// threads when this is more than 1, where formatting a `Ty` or an `Rvalue` with `{:?}`
+    /// panics because there is no type context.
+    pub threads: usize,
+    /// Largest number of block visits per function
+    pub max_iterations: Option<usize>,
+    /// Longest time the analysis of one function may take
+    pub timeout: Option<Duration>,
+    /// What happens to a function that goes over `max_iterations` or `timeout`
+    pub on_exceeded: OnExceeded,
+}
+
+/// What to do with a function whose analysis doesn't converge within its budget
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnExceeded {
+    /// Give up on precision: every block gets the top state, which is sound
+    Top,
+    /// Don't check the function at all
+    Abort,
+}
+
+/// Why the analysis of a function was stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exceeded {
+    Iterations(usize),
+    Timeout(Duration),
+}
+
+impl fmt::Display for Exceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Exceeded::Iterations(n) => write!(f, "did not converge within {} iterations", n),
+            Exceeded::Timeout(t) => write!(f, "did not converge within {:?}", t),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            overflow: Overflow::Wrapping,
+            strategy: Strategy::ReversePostorder,
+            print_metrics: false,
+            sparse: false,
+            threads: 1,
+            max_iterations: None,
+            timeout: None,
+            on_exceeded: OnExceeded::Top,
+        }
+    }
+}
@@ -0,0 +1,454 @@
+use crate::int::{ConstInt, IntType};
+use crate::lattice::{Arith, Overflow, SimpleLattice};
+use rustc::mir::interpret::ConstValue;
+use rustc::mir::{BinOp, CastKind, UnOp};
+use rustc::ty::layout::Size;
+use rustc::ty::TyKind;
+
+/// Congruence of an integer: `x ≡ residue (mod modulus)`. Parity is the case `modulus == 2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Congruence {
+    Bottom,
+    /// All values `residue + k * modulus`. A modulus of 0 means the value is `residue`, otherwise
+    /// `0 <= residue < modulus`.
+    Mod {
+        modulus: i128,
+        residue: i128,
+    },
+}
+
+use Congruence::*;
+
+/// `a` modulo `m`, between 0 and `m`, for `m > 0`
+fn modulo(a: i128, m: i128) -> i128 {
+    let r = a % m;
+    if r < 0 {
+        r + m
+    } else {
+        r
+    }
+}
+
+/// Greatest common divisor of non-negative numbers
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+/// Inverse of `a` modulo `m`, for coprime `a` and `m > 0`
+fn inverse(a: i128, m: i128) -> i128 {
+    let (mut r0, mut r1) = (modulo(a, m), m);
+    let (mut s0, mut s1) = (1, 0);
+    while r1 != 0 {
+        let q = r0 / r1;
+        let (r, s) = (r0 - q * r1, s0 - q * s1);
+        r0 = r1;
+        r1 = r;
+        s0 = s1;
+        s1 = s;
+    }
+    modulo(s0, m)
+}
+
+/// Exponent of the largest power of 2 that divides `m` (`m == 0` is divided by all of them)
+fn twos(m: i128) -> u32 {
+    if m == 0 {
+        127
+    } else {
+        m.trailing_zeros().min(126)
+    }
+}
+
+impl Congruence {
+    /// `x ≡ residue (mod modulus)`, normalized
+    pub fn new(modulus: i128, residue: i128) -> Self {
+        match modulus.checked_abs() {
+            Some(0) => Mod {
+                modulus: 0,
+                residue,
+            },
+            Some(modulus) => Mod {
+                modulus,
+                residue: modulo(residue, modulus),
+            },
+            None => Congruence::top(),
+        }
+    }
+
+    pub fn constant(x: i128) -> Self {
+        Congruence::new(0, x)
+    }
+
+    /// Even or odd values
+    pub fn parity(odd: bool) -> Self {
+        Congruence::new(2, odd as i128)
+    }
+
+    pub fn is_even(&self) -> bool {
+        match *self {
+            Mod { modulus, residue } => modulus % 2 == 0 && residue % 2 == 0,
+            Bottom => false,
+        }
+    }
+
+    pub fn is_odd(&self) -> bool {
+        match *self {
+            Mod { modulus, residue } => modulus % 2 == 0 && residue % 2 != 0,
+            Bottom => false,
+        }
+    }
+
+    /// Whether all values are multiples of `m`, e.g. aligned to `m` bytes
+    pub fn is_multiple_of(&self, m: i128) -> bool {
+        match *self {
+            Mod { modulus, residue } => m != 0 && modulus % m == 0 && residue % m == 0,
+            Bottom => true,
+        }
+    }
+
+    pub fn contains(&self, x: i128) -> bool {
+        match *self {
+            Mod {
+                modulus: 0,
+                residue,
+            } => x == residue,
+            Mod { modulus, residue } => modulo(x, modulus) == residue,
+            Bottom => false,
+        }
+    }
+
+    /// Values in both congruences (by the Chinese remainder theorem)
+    pub fn meet(&self, other: &Self) -> Self {
+        let (m1, a1, m2, a2) = match (*self, *other) {
+            (
+                Mod {
+                    modulus: 0,
+                    residue,
+                },
+                c,
+            )
+            | (
+                c,
+                Mod {
+                    modulus: 0,
+                    residue,
+                },
+            ) => {
+                return if c.contains(residue) {
+                    Congruence::constant(residue)
+                } else {
+                    Bottom
+                };
+            }
+            (
+                Mod {
+                    modulus: m1,
+                    residue: a1,
+                },
+                Mod {
+                    modulus: m2,
+                    residue: a2,
+                },
+            ) => (m1, a1, m2, a2),
+            _ => return Bottom,
+        };
+        let g = gcd(m1, m2);
+        if (a2 - a1) % g != 0 {
+            return Bottom;
+        }
+        // x = a1 + m1 * t, where m1 * t ≡ a2 - a1 (mod m2)
+        let m = m2 / g;
+        let t = modulo((a2 - a1) / g, m)
+            .checked_mul(inverse(m1 / g, m))
+            .map(|t| modulo(t, m));
+        let lcm = (m1 / g).checked_mul(m2);
+        match (t, lcm) {
+            (Some(t), Some(lcm)) => Congruence::new(lcm, a1 + m1 * t),
+            // Either congruence contains the meet
+            _ if m1 >= m2 => *self,
+            _ => *other,
+        }
+    }
+}
+
+/// Congruence of the result `residue + k * modulus` of an arithmetic operation on `ty`, after
+/// overflow. `None` stands for an overflow of the `i128`s.
+fn fit(modulus: Option<i128>, residue: Option<i128>, arith: &Arith) -> Congruence {
+    let (modulus, residue) = match (modulus, residue) {
+        (Some(modulus), Some(residue)) => (modulus, residue),
+        _ => return Congruence::top(),
+    };
+    let ty = match arith.ty {
+        Some(ty) => ty,
+        None => return Congruence::new(modulus, residue),
+    };
+    if modulus == 0 && !ty.contains(residue) {
+        return match ty.wrap(residue) {
+            _ if arith.is_exact() => Bottom,
+            Some(residue) => Congruence::constant(residue),
+            None => Congruence::top(),
+        };
+    }
+    if modulus == 0 || arith.is_exact() {
+        return Congruence::new(modulus, residue);
+    }
+    // Wrapping around subtracts multiples of 2^bits, which keeps congruences modulo its divisors
+    let k = twos(modulus).min(ty.bits() as u32);
+    Congruence::new(1 << k, residue)
+}
+
+fn binop(op: &BinOp, m1: i128, a1: i128, m2: i128, a2: i128, arith: &Arith) -> Congruence {
+    let wrapping = Arith {
+        overflow: Overflow::Wrapping,
+        ..*arith
+    };
+    match op {
+        BinOp::Add => fit(Some(gcd(m1, m2)), a1.checked_add(a2), arith),
+        BinOp::Sub => fit(Some(gcd(m1, m2)), a1.checked_sub(a2), arith),
+        BinOp::Mul => {
+            let modulus = a1.checked_mul(m2).and_then(|x| {
+                let y = a2.checked_mul(m1)?;
+                let z = m1.checked_mul(m2)?;
+                Some(gcd(gcd(x.checked_abs()?, y.checked_abs()?), z))
+            });
+            fit(modulus, a1.checked_mul(a2), arith)
+        }
+        // Division by zero and `MIN / -1` panic
+        BinOp::Div | BinOp::Rem if m2 == 0 && a2 == 0 => Bottom,
+        BinOp::Div if m1 == 0 && m2 == 0 => match a1.checked_div(a2) {
+            Some(q) => fit(
+                Some(0),
+                Some(q),
+                &Arith {
+                    overflow: Overflow::Panicking,
+                    ..*arith
+                },
+            ),
+            None => Bottom,
+        },
+        // (a1 + k * m1) / c == a1 / c + k * (m1 / c) if c divides both
+        BinOp::Div if m2 == 0 && a1 % a2 == 0 && m1 % a2 == 0 => Congruence::new(m1 / a2, a1 / a2),
+        BinOp::Div => Congruence::top(),
+        BinOp::Rem if m1 == 0 && m2 == 0 => match a1.checked_rem(a2) {
+            Some(r) => Congruence::constant(r),
+            None => Bottom,
+        },
+        // The remainder differs from the dividend by a multiple of the divisor
+        BinOp::Rem => match a2.checked_abs() {
+            Some(a2) => Congruence::new(gcd(m1, gcd(m2, a2)), a1),
+            None => Congruence::top(),
+        },
+        BinOp::Shl | BinOp::Shr if m2 != 0 => Congruence::top(),
+        BinOp::Shl | BinOp::Shr => {
+            let bits = match arith.ty {
+                Some(ty) => i128::from(ty.bits()),
+                None => return Congruence::top(),
+            };
+            if arith.is_exact() && (a2 < 0 || a2 >= bits) {
+                return Bottom;
+            }
+            let k = (a2 & (bits - 1)) as u32;
+            match op {
+                // Bits shifted out are lost even with overflow checks
+                BinOp::Shl if k < 126 => binop(&BinOp::Mul, m1, a1, 0, 1 << k, &wrapping),
+                // Shifting is flooring division by 2^k
+                BinOp::Shr if m1 == 0 => Congruence::constant(a1 >> k),
+                BinOp::Shr if twos(m1) >= k => Congruence::new(m1 >> k, a1 >> k),
+                _ => Congruence::top(),
+            }
+        }
+        BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor => {
+            let result = match op {
+                BinOp::BitAnd => a1 & a2,
+                BinOp::BitOr => a1 | a2,
+                _ => a1 ^ a2,
+            };
+            if m1 == 0 && m2 == 0 {
+                return Congruence::constant(result);
+            }
+            // `x & (2^k - 1)` is `x` modulo 2^k
+            let mask =
+                |m: i128, a: i128| m == 0 && a >= 0 && a < i128::max_value() && a & (a + 1) == 0;
+            match op {
+                BinOp::BitAnd if mask(m2, a2) && twos(m1) >= twos(a2 + 1) => {
+                    return Congruence::constant(modulo(a1, a2 + 1));
+                }
+                BinOp::BitAnd if mask(m1, a1) && twos(m2) >= twos(a1 + 1) => {
+                    return Congruence::constant(modulo(a2, a1 + 1));
+                }
+                _ => {}
+            }
+            // The low bits of both operands are known, so the low bits of the result are too
+            let k = twos(m1).min(twos(m2));
+            if k == 0 {
+                Congruence::top()
+            } else {
+                Congruence::new(1 << k, result)
+            }
+        }
+        // Comparisons give booleans, and offsets pointers
+        _ => Congruence::top(),
+    }
+}
+
+impl SimpleLattice for Congruence {
+    fn applies(ty: &TyKind) -> bool {
+        match ty {
+            TyKind::Int(_) | TyKind::Uint(_) => true,
+            _ => false,
+        }
+    }
+
+    fn bot() -> Self {
+        Bottom
+    }
+
+    fn top() -> Self {
+        Mod {
+            modulus: 1,
+            residue: 0,
+        }
+    }
+
+    fn join(op1: &Self, op2: &Self) -> Self {
+        match (*op1, *op2) {
+            (Bottom, a) | (a, Bottom) => a,
+            (
+                Mod {
+                    modulus: m1,
+                    residue: a1,
+                },
+                Mod {
+                    modulus: m2,
+                    residue: a2,
+                },
+            ) => match a1.checked_sub(a2).and_then(i128::checked_abs) {
+                Some(d) => Congruence::new(gcd(gcd(m1, m2), d), a1),
+                None => Congruence::top(),
+            },
+        }
+    }
+
+    fn alpha(ty: &TyKind, size: Size, a: ConstValue) -> Self {
+        ConstInt::decode(ty, size, a)
+            .and_then(ConstInt::to_i128)
+            .map_or(Congruence::top(), Congruence::constant)
+    }
+
+    fn flow_binop(op: &BinOp, arg1: &Self, arg2: &Self, arith: &Arith) -> Self {
+        match (*arg1, *arg2) {
+            (
+                Mod {
+                    modulus: m1,
+                    residue: a1,
+                },
+                Mod {
+                    modulus: m2,
+                    residue: a2,
+                },
+            ) => binop(op, m1, a1, m2, a2, arith),
+            _ => Bottom,
+        }
+    }
+
+    fn flow_unop(op: &UnOp, arg: &Self, arith: &Arith) -> Self {
+        let (modulus, residue) = match *arg {
+            Mod { modulus, residue } => (modulus, residue),
+            Bottom => return Bottom,
+        };
+        match op {
+            UnOp::Neg => fit(Some(modulus), residue.checked_neg(), arith),
+            // `!x` is `-x - 1`, modulo 2^bits for unsigned integers
+            UnOp::Not => {
+                let overflow = match arith.ty {
+                    Some(ty) if ty.signed => Overflow::Panicking,
+                    _ => Overflow::Wrapping,
+                };
+                fit(
+                    Some(modulus),
+                    residue.checked_neg().and_then(|x| x.checked_sub(1)),
+                    &Arith { overflow, ..*arith },
+                )
+            }
+        }
+    }
+
+    fn flow_cond_true(op: &BinOp, arg1: &Self, arg2: &Self) -> (Self, Self) {
+        match (op, *arg1, *arg2) {
+            (BinOp::Eq, _, _) => {
+                let both = arg1.meet(arg2);
+                (both, both)
+            }
+            (
+                BinOp::Ne,
+                Mod {
+                    modulus: 0,
+                    residue: a1,
+                },
+                Mod {
+                    modulus: 0,
+                    residue: a2,
+                },
+            ) if a1 == a2 => (Bottom, Bottom),
+            _ => (*arg1, *arg2),
+        }
+    }
+
+    fn flow_cond_false(op: &BinOp, arg1: &Self, arg2: &Self) -> (Self, Self) {
+        match op {
+            BinOp::Eq => Self::flow_cond_true(&BinOp::Ne, arg1, arg2),
+            BinOp::Ne => Self::flow_cond_true(&BinOp::Eq, arg1, arg2),
+            _ => (*arg1, *arg2),
+        }
+    }
+
+    fn flow_cast(kind: &CastKind, from_ty: &TyKind, to_ty: &TyKind, arg: &Self) -> Self {
+        let (modulus, residue) = match (kind, *arg) {
+            (_, Bottom) => return Bottom,
+            (CastKind::Misc, Mod { modulus, residue }) if Self::applies(from_ty) => {
+                (modulus, residue)
+            }
+            _ => return Congruence::top(),
+        };
+        // The value is unchanged if the target type has all the values of the source type,
+        // whatever the width of `isize` and `usize`
+        let from = IntType::of(from_ty, Size::from_bits(64));
+        let to = IntType::of(to_ty, Size::from_bits(16));
+        match (from, to) {
+            (Some(from), Some(to))
+                if (from.bits() < 128 || from == to)
+                    && to.contains(from.min())
+                    && to.contains(from.max()) =>
+            {
+                *arg
+            }
+            // Otherwise it is truncated, or extended from the same low bits
+            (Some(_), Some(to)) => {
+                let from = IntType::of(from_ty, Size::from_bits(16)).unwrap();
+                let k = twos(modulus).min(from.bits().min(to.bits()) as u32);
+                Congruence::new(1 << k, residue)
+            }
+            _ => Congruence::top(),
+        }
+    }
+
+    fn flow_rem_cond(dividend: &Self, divisor: &Self, remainder: &Self) -> Self {
+        match (*divisor, *remainder) {
+            // `x % c == r` means `x ≡ r (mod c)`
+            (
+                Mod {
+                    modulus: 0,
+                    residue: c,
+                },
+                Mod { modulus, residue },
+            ) if c != 0 && c != i128::min_value() => {
+                dividend.meet(&Congruence::new(gcd(c.abs(), modulus), residue))
+            }
+            _ => *dividend,
+        }
+    }
+}
@@ -0,0 +1,301 @@
+use crate::equalities::Equalities;
+use crate::lattice::{Context, Lattice};
+use rustc::mir::{BasicBlock, Local, LocalDecl, Operand, Place, Rvalue, Statement, TerminatorKind};
+use rustc_data_structures::indexed_vec::IndexVec;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// How to get back to `Partitioning::MAX` disjuncts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Merge {
+    /// Joins all the disjuncts into one
+    All,
+    /// Joins the two disjuncts that took the same way at the most branches before the merge, one
+    /// pair at a time. Without `Partitioning::BY_BRANCH`, this joins the first two disjuncts.
+    SameBranches,
+}
+
+/// Parameters of a `Disjunctive` lattice. The bound is an associated constant rather than a const
+/// generic parameter, which the toolchain doesn't support yet.
+pub trait Partitioning {
+    /// Largest number of disjuncts in a state
+    const MAX: usize;
+    const MERGE: Merge = Merge::SameBranches;
+    /// Whether disjuncts remember the outcomes of the branches they went through (trace
+    /// partitioning), so that merging can keep the paths that differ the most apart
+    const BY_BRANCH: bool = false;
+}
+
+/// Up to four disjuncts, partitioned by branch
+pub struct FourPaths;
+
+impl Partitioning for FourPaths {
+    const MAX: usize = 4;
+    const BY_BRANCH: bool = true;
+}
+
+/// Outcomes of the latest branches of a disjunct, most recent in the lowest bit
+type Trace = u64;
+
+/// Disjunction of up to `P::MAX` states of `L`, which keeps correlations between locals that a
+/// join would lose (e.g. "`x > 0` and `y > 0`, or both are negative"). Bottom has no disjuncts.
+pub struct Disjunctive<L, P> {
+    disjuncts: Vec<(L, Trace)>,
+    partitioning: PhantomData<fn() -> P>,
+}
+
+impl<L: Lattice, P: Partitioning> Disjunctive<L, P> {
+    fn from_disjuncts(disjuncts: Vec<(L, Trace)>) -> Self {
+        let mut result = Disjunctive {
+            disjuncts,
+            partitioning: PhantomData,
+        };
+        result.normalize();
+        result
+    }
+
+    pub fn disjuncts(&self) -> impl Iterator<Item = &L> {
+        self.disjuncts.iter().map(|(state, _)| state)
+    }
+
+    /// Join of all the disjuncts
+    pub fn collapse(&self, decls: &IndexVec<Local, LocalDecl>) -> L {
+        self.disjuncts()
+            .fold(L::bot(decls), |acc, state| L::join(&acc, state))
+    }
+
+    /// Removes the disjuncts included in another one, then merges them down to `P::MAX`
+    fn normalize(&mut self) {
+        let mut i = 0;
+        while i < self.disjuncts.len() {
+            let state = &self.disjuncts[i].0;
+            let subsumed = self.disjuncts.iter().enumerate().any(|(j, (other, _))| {
+                // Of equal disjuncts, the first one is kept
+                if state == other {
+                    j < i
+                } else {
+                    L::join(state, other) == *other
+                }
+            });
+            if subsumed {
+                self.disjuncts.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+        if self.disjuncts.len() <= P::MAX.max(1) {
+            return;
+        }
+        match P::MERGE {
+            Merge::All => {
+                let trace = self.disjuncts[0].1;
+                let state = self.disjuncts[1..]
+                    .iter()
+                    .fold(self.disjuncts[0].0.clone(), |acc, (state, _)| {
+                        L::join(&acc, state)
+                    });
+                self.disjuncts = vec![(state, trace)];
+            }
+            Merge::SameBranches => {
+                while self.disjuncts.len() > P::MAX.max(1) {
+                    let mut best = (0, 1);
+                    let mut best_shared = 0;
+                    for i in 0..self.disjuncts.len() {
+                        for j in i + 1..self.disjuncts.len() {
+                            let shared =
+                                (self.disjuncts[i].1 ^ self.disjuncts[j].1).trailing_zeros();
+                            if shared > best_shared {
+                                best = (i, j);
+                                best_shared = shared;
+                            }
+                        }
+                    }
+                    let (state, _) = self.disjuncts.remove(best.1);
+                    let merged = &mut self.disjuncts[best.0].0;
+                    *merged = L::join(merged, &state);
+                }
+            }
+        }
+    }
+
+    /// Splits each disjunct in two with `f`, which refines its first argument into the state of
+    /// one edge and its second argument into the state of the other edge. `branch` tells whether
+    /// the edges are those of a branch, to be recorded in the traces.
+    fn split<F>(&mut self, other: &mut Self, branch: bool, mut f: F)
+    where
+        F: FnMut(&mut L, &mut L),
+    {
+        let mut others = Vec::with_capacity(self.disjuncts.len());
+        for (state, _) in self.disjuncts.iter_mut() {
+            let mut other = state.clone_for_branch();
+            f(state, &mut other);
+            others.push(other);
+        }
+        let by_branch = branch && P::BY_BRANCH;
+        let mut traces = Vec::with_capacity(self.disjuncts.len());
+        for (_, trace) in self.disjuncts.iter_mut() {
+            traces.push(if by_branch { *trace << 1 | 1 } else { *trace });
+            if by_branch {
+                *trace <<= 1;
+            }
+        }
+        *other = Self::from_disjuncts(others.into_iter().zip(traces).collect());
+        self.normalize();
+    }
+}
+
+// Implemented by hand, since deriving them would require `P` to implement them too
+
+impl<L: Clone, P> Clone for Disjunctive<L, P> {
+    fn clone(&self) -> Self {
+        Disjunctive {
+            disjuncts: self.disjuncts.clone(),
+            partitioning: PhantomData,
+        }
+    }
+}
+
+/// Equality of the sets of states, whatever their order and traces
+impl<L: PartialEq, P> PartialEq for Disjunctive<L, P> {
+    fn eq(&self, other: &Self) -> bool {
+        let included = |a: &Self, b: &Self| {
+            a.disjuncts
+                .iter()
+                .all(|(state, _)| b.disjuncts.iter().any(|(other, _)| state == other))
+        };
+        included(self, other) && included(other, self)
+    }
+}
+
+impl<L: Eq, P> Eq for Disjunctive<L, P> {}
+
+impl<L: fmt::Debug, P> fmt::Debug for Disjunctive<L, P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list()
+            .entries(self.disjuncts.iter().map(|(state, _)| state))
+            .finish()
+    }
+}
+
+impl<L: Lattice, P: Partitioning> Lattice for Disjunctive<L, P> {
+    fn bot(_decls: &IndexVec<Local, LocalDecl>) -> Self {
+        Self::from_disjuncts(Vec::new())
+    }
+
+    fn top(decls: &IndexVec<Local, LocalDecl>) -> Self {
+        Self::from_disjuncts(vec![(L::top(decls), 0)])
+    }
+
+    fn is_unreachable(&self) -> bool {
+        self.disjuncts.is_empty()
+    }
+
+    fn join(op1: &Self, op2: &Self) -> Self {
+        let mut disjuncts = op1.disjuncts.clone();
+        disjuncts.extend(op2.disjuncts.iter().cloned());
+        Self::from_disjuncts(disjuncts)
+    }
+
+    /// Collapses the disjuncts into one to ensure termination, unless `op2` adds nothing to `op1`
+    fn widen<'tcx>(op1: &Self, op2: &Self, ctx: &Context<'_, 'tcx>) -> Self {
+        if Self::join(op1, op2) == *op1 {
+            return op1.clone();
+        }
+        let old = op1.collapse(ctx.decls);
+        let new = L::join(&old, &op2.collapse(ctx.decls));
+        Self::from_disjuncts(vec![(L::widen(&old, &new, ctx), 0)])
+    }
+
+    fn apply_assign<'tcx>(
+        &mut self,
+        local: Local,
+        rvalue: &Box<Rvalue<'tcx>>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        for (state, _) in self.disjuncts.iter_mut() {
+            state.apply_assign(local, rvalue, equalities, ctx);
+        }
+        self.normalize();
+    }
+
+    fn apply_branch<'tcx>(
+        &mut self,
+        on_true: &mut Self,
+        rvalue: &Box<Rvalue<'tcx>>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        self.split(on_true, true, |state, on_true| {
+            state.apply_branch(on_true, rvalue, equalities, ctx)
+        });
+    }
+
+    fn apply_function_call(&mut self, func: &Operand, args: &Vec<Operand>, destination: &Place) {
+        for (state, _) in self.disjuncts.iter_mut() {
+            state.apply_function_call(func, args, destination);
+        }
+        self.normalize();
+    }
+
+    fn clone_for_branch(&self) -> Self {
+        Disjunctive {
+            disjuncts: self
+                .disjuncts
+                .iter()
+                .map(|(state, trace)| (state.clone_for_branch(), *trace))
+                .collect(),
+            partitioning: PhantomData,
+        }
+    }
+
+    fn apply_statement<'tcx>(
+        &mut self,
+        stmt: &Statement<'tcx>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        for (state, _) in self.disjuncts.iter_mut() {
+            state.apply_statement(stmt, equalities, ctx);
+        }
+        self.normalize();
+    }
+
+    fn apply_remainder<'tcx>(
+        &mut self,
+        dividend: &Operand<'tcx>,
+        divisor: &Operand<'tcx>,
+        remainder: &Operand<'tcx>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        for (state, _) in self.disjuncts.iter_mut() {
+            state.apply_remainder(dividend, divisor, remainder, equalities, ctx);
+        }
+        self.normalize();
+    }
+
+    fn apply_assert<'tcx>(
+        &mut self,
+        on_failure: &mut Self,
+        cond: &Operand<'tcx>,
+        expected: bool,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        self.split(on_failure, false, |state, on_failure| {
+            state.apply_assert(on_failure, cond, expected, ctx)
+        });
+    }
+
+    fn apply_terminator<'tcx>(
+        &mut self,
+        kind: &TerminatorKind<'tcx>,
+        target: BasicBlock,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        for (state, _) in self.disjuncts.iter_mut() {
+            state.apply_terminator(kind, target, ctx);
+        }
+        self.normalize();
+    }
+}
@@ -0,0 +1,211 @@
+use crate::cfg::predecessors;
+use rustc::mir::{BasicBlock, BasicBlockData, START_BLOCK};
+use rustc_data_structures::indexed_vec::{Idx, IndexVec};
+
+/// Dominator (or post-dominator) tree of a function, computed with the algorithm of Cooper, Harvey
+/// and Kennedy. Blocks that are unreachable (or, for post-dominators, that can't reach an exit) are
+/// not in the tree.
+#[derive(Debug, Clone)]
+pub struct DominatorTree {
+    idom: IndexVec<BasicBlock, Option<BasicBlock>>,
+    depth: IndexVec<BasicBlock, Option<usize>>,
+    roots: Vec<BasicBlock>,
+    children: IndexVec<BasicBlock, Vec<BasicBlock>>,
+    frontiers: IndexVec<BasicBlock, Vec<BasicBlock>>,
+}
+
+impl DominatorTree {
+    /// A dominates B if every path from the start block to B goes through A
+    pub fn dominators(blocks: &IndexVec<BasicBlock, BasicBlockData>) -> Self {
+        let succs = blocks
+            .iter()
+            .map(|data| data.terminator().successors().map(|b| b.index()).collect())
+            .collect::<Vec<Vec<usize>>>();
+        Self::build(blocks.len(), &succs, START_BLOCK.index())
+    }
+
+    /// A post-dominates B if every path from B to an exit of the function goes through A. Blocks
+    /// without successors (returns, resumes, ...) are the exits, so there can be several roots.
+    pub fn post_dominators(blocks: &IndexVec<BasicBlock, BasicBlockData>) -> Self {
+        // The reversed graph, with an extra node that has an edge to every exit
+        let exit = blocks.len();
+        let mut succs = predecessors(blocks)
+            .into_iter()
+            .map(|preds| preds.into_iter().map(BasicBlock::index).collect())
+            .collect::<Vec<Vec<usize>>>();
+        succs.push(
+            blocks
+                .iter_enumerated()
+                .filter(|(_, data)| data.terminator().successors().next().is_none())
+                .map(|(b, _)| b.index())
+                .collect(),
+        );
+        Self::build(blocks.len(), &succs, exit)
+    }
+
+    /// `succs` may have one extra node, which is then used as the entry and left out of the tree
+    fn build(num_blocks: usize, succs: &[Vec<usize>], entry: usize) -> Self {
+        let n = succs.len();
+        let mut preds = vec![Vec::new(); n];
+        for (node, node_succs) in succs.iter().enumerate() {
+            for &suc in node_succs {
+                preds[suc].push(node);
+            }
+        }
+
+        // Reverse postorder of the nodes reachable from the entry
+        let mut rpo = Vec::with_capacity(n);
+        let mut visited = vec![false; n];
+        let mut stack = vec![(entry, 0)];
+        visited[entry] = true;
+        while let Some(&(node, pos)) = stack.last() {
+            stack.last_mut().unwrap().1 += 1;
+            match succs[node].get(pos) {
+                Some(&suc) => {
+                    if !visited[suc] {
+                        visited[suc] = true;
+                        stack.push((suc, 0));
+                    }
+                }
+                None => {
+                    stack.pop();
+                    rpo.push(node);
+                }
+            }
+        }
+        rpo.reverse();
+        let mut rpo_index = vec![None; n];
+        for (i, &node) in rpo.iter().enumerate() {
+            rpo_index[node] = Some(i);
+        }
+
+        let mut idom = vec![None; n];
+        idom[entry] = Some(entry);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &node in rpo.iter().skip(1) {
+                let mut new_idom = None;
+                for &pred in preds[node].iter() {
+                    if idom[pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(other) => intersect(&idom, &rpo_index, pred, other),
+                    });
+                }
+                if new_idom.is_some() && idom[node] != new_idom {
+                    idom[node] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        // Dominance frontiers, walking up from the predecessors of each join point
+        let mut frontiers = IndexVec::from_elem_n(Vec::new(), num_blocks);
+        for &node in rpo.iter() {
+            let reachable_preds = preds[node]
+                .iter()
+                .filter(|&&pred| idom[pred].is_some())
+                .collect::<Vec<_>>();
+            if reachable_preds.len() < 2 {
+                continue;
+            }
+            for &pred in reachable_preds {
+                let mut runner = pred;
+                while Some(runner) != idom[node] {
+                    // The extra entry node is not a block
+                    if runner < num_blocks {
+                        let frontier: &mut Vec<BasicBlock> =
+                            &mut frontiers[BasicBlock::new(runner)];
+                        if !frontier.contains(&BasicBlock::new(node)) {
+                            frontier.push(BasicBlock::new(node));
+                        }
+                    }
+                    runner = idom[runner].unwrap();
+                }
+            }
+        }
+
+        let mut tree = DominatorTree {
+            idom: IndexVec::from_elem_n(None, num_blocks),
+            depth: IndexVec::from_elem_n(None, num_blocks),
+            roots: Vec::new(),
+            children: IndexVec::from_elem_n(Vec::new(), num_blocks),
+            frontiers,
+        };
+        // Parents come before their children in reverse postorder
+        for &node in rpo.iter().filter(|&&node| node < num_blocks) {
+            let block = BasicBlock::new(node);
+            let parent = idom[node].unwrap();
+            if node == entry || (parent == entry && entry >= num_blocks) {
+                tree.roots.push(block);
+                tree.depth[block] = Some(0);
+            } else {
+                let parent = BasicBlock::new(parent);
+                tree.idom[block] = Some(parent);
+                tree.depth[block] = tree.depth[parent].map(|depth| depth + 1);
+                tree.children[parent].push(block);
+            }
+        }
+        tree
+    }
+
+    /// The closest strict dominator of `block`. None for roots and for blocks outside the tree.
+    pub fn immediate_dominator(&self, block: BasicBlock) -> Option<BasicBlock> {
+        self.idom[block]
+    }
+
+    /// Whether `a` dominates `b`. Every block in the tree dominates itself.
+    pub fn dominates(&self, a: BasicBlock, b: BasicBlock) -> bool {
+        let (depth_a, mut depth_b) = match (self.depth[a], self.depth[b]) {
+            (Some(depth_a), Some(depth_b)) => (depth_a, depth_b),
+            _ => return false,
+        };
+        let mut b = b;
+        while depth_b > depth_a {
+            b = self.idom[b].unwrap();
+            depth_b -= 1;
+        }
+        a == b
+    }
+
+    pub fn is_reachable(&self, block: BasicBlock) -> bool {
+        self.depth[block].is_some()
+    }
+
+    /// Blocks without an immediate dominator: the start block, or the exits for post-dominators
+    pub fn roots(&self) -> &[BasicBlock] {
+        &self.roots
+    }
+
+    /// Blocks immediately dominated by `block`
+    pub fn children(&self, block: BasicBlock) -> &[BasicBlock] {
+        &self.children[block]
+    }
+
+    /// Blocks where the dominance of `block` ends: they are not strictly dominated by it, but one of
+    /// their predecessors is dominated by it. For post-dominators, these are the blocks `block` is
+    /// control dependent on.
+    pub fn frontier(&self, block: BasicBlock) -> &[BasicBlock] {
+        &self.frontiers[block]
+    }
+}
+
+fn intersect(
+    idom: &[Option<usize>],
+    rpo_index: &[Option<usize>],
+    mut a: usize,
+    mut b: usize,
+) -> usize {
+    while a != b {
+        while rpo_index[a] > rpo_index[b] {
+            a = idom[a].unwrap();
+        }
+        while rpo_index[b] > rpo_index[a] {
+            b = idom[b].unwrap();
+        }
+    }
+    a
+}
@@ -0,0 +1,262 @@
+use crate::lattice::{base_local, operand_local};
+use rustc::mir::{
+    BasicBlock, BinOp, Local, Location, Place, PlaceBase, Rvalue, Statement, StatementKind,
+    TerminatorKind,
+};
+use rustc_data_structures::indexed_vec::IndexVec;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Locals that must hold the same value, because one was copied to the other and neither was
+/// written since. Each class has a representative that keeps the list of its members, so finding,
+/// merging and splitting classes cost the size of the classes involved rather than the number of
+/// locals. Representatives depend on the order of the updates, so equal partitions are compared
+/// by the smallest local of each class.
+///
+/// Along with the classes, this remembers the locals assigned a remainder `x % m`, so that a branch
+/// on the remainder can also refine `x`.
+#[derive(Debug, Clone, Eq)]
+pub struct Equalities {
+    representative: IndexVec<Local, Local>,
+    /// Members of the class of each representative, in no particular order. Empty for a local
+    /// alone in its class, and for locals that are not representatives.
+    members: IndexVec<Local, Vec<Local>>,
+    /// Location of the `local = x % m` statement that assigned each local, and the locals holding
+    /// `x` and `m` (`None` for constants), while none of them was written since. When one of those
+    /// is written, another local equal to it takes its place.
+    remainders: HashMap<Local, (Location, Option<Local>, Option<Local>)>,
+    /// Locals that are borrowed mutably somewhere in the function. They may be written through a
+    /// pointer at any time, so they are never put in a class.
+    escaping: Arc<IndexVec<Local, bool>>,
+}
+
+impl Equalities {
+    /// Each local alone in its class. `escaping` is computed by `lattice::escaping`.
+    pub fn new(escaping: &Arc<IndexVec<Local, bool>>) -> Self {
+        Equalities {
+            representative: escaping.indices().collect(),
+            members: IndexVec::from_elem_n(Vec::new(), escaping.len()),
+            remainders: HashMap::new(),
+            escaping: escaping.clone(),
+        }
+    }
+
+    pub fn are_equal(&self, a: Local, b: Local) -> bool {
+        self.representative[a] == self.representative[b]
+    }
+
+    /// Locals equal to `local`, including itself
+    pub fn class(&self, local: Local) -> impl Iterator<Item = Local> + '_ {
+        let members = &self.members[self.representative[local]];
+        let alone = if members.is_empty() {
+            Some(local)
+        } else {
+            None
+        };
+        alone.into_iter().chain(members.iter().cloned())
+    }
+
+    /// Location of the `x % m` statement whose result `local` (or a local equal to it) holds, and
+    /// the locals that hold `x` and `m` now (`None` for constants)
+    pub fn remainder(&self, local: Local) -> Option<(Location, Option<Local>, Option<Local>)> {
+        self.class(local)
+            .filter_map(|local| self.remainders.get(&local))
+            .cloned()
+            .next()
+    }
+
+    /// Takes the members of the class represented by `representative`
+    fn take_members(&mut self, representative: Local) -> Vec<Local> {
+        let members = std::mem::replace(&mut self.members[representative], Vec::new());
+        if members.is_empty() {
+            vec![representative]
+        } else {
+            members
+        }
+    }
+
+    fn union(&mut self, a: Local, b: Local) {
+        if self.escaping[a] || self.escaping[b] {
+            return;
+        }
+        let (a, b) = (self.representative[a], self.representative[b]);
+        if a == b {
+            return;
+        }
+        let (mut kept, mut merged) = (self.take_members(a), self.take_members(b));
+        // The smaller class joins the larger one
+        let (representative, merged) = if kept.len() < merged.len() {
+            std::mem::swap(&mut kept, &mut merged);
+            (b, merged)
+        } else {
+            (a, merged)
+        };
+        for &local in merged.iter() {
+            self.representative[local] = representative;
+        }
+        kept.extend(merged);
+        self.members[representative] = kept;
+    }
+
+    /// Takes `local` out of its class, when it is written
+    fn kill(&mut self, local: Local) {
+        if !self.remainders.is_empty() {
+            // Operands are often copied to temporaries that die before the remainder is compared
+            let substitute = self.class(local).find(|&other| other != local);
+            self.remainders.retain(|&rem, (_, dividend, divisor)| {
+                if rem == local {
+                    return false;
+                }
+                for operand in [dividend, divisor].iter_mut() {
+                    if **operand == Some(local) {
+                        if substitute.is_none() {
+                            return false;
+                        }
+                        **operand = substitute;
+                    }
+                }
+                true
+            });
+        }
+        let representative = self.representative[local];
+        if self.members[representative].is_empty() {
+            return;
+        }
+        let mut members = self.take_members(representative);
+        let position = members.iter().position(|&other| other == local).unwrap();
+        members.swap_remove(position);
+        self.representative[local] = local;
+        if members.len() == 1 {
+            self.representative[members[0]] = members[0];
+            return;
+        }
+        // Another member represents the rest of the class
+        let representative = if representative == local {
+            let next = members[0];
+            for &other in members.iter() {
+                self.representative[other] = next;
+            }
+            next
+        } else {
+            representative
+        };
+        self.members[representative] = members;
+    }
+
+    fn kill_place(&mut self, place: &Place) {
+        if let Some(local) = base_local(place) {
+            self.kill(local);
+        }
+    }
+
+    /// Smallest local of the class of each local, which only depends on the partition
+    fn smallest(&self) -> IndexVec<Local, Local> {
+        let mut smallest: IndexVec<Local, Local> = self.representative.indices().collect();
+        for members in self.members.iter() {
+            if let Some(&min) = members.iter().min() {
+                for &local in members.iter() {
+                    smallest[local] = min;
+                }
+            }
+        }
+        smallest
+    }
+
+    /// Equalities that hold in both `op1` and `op2`
+    pub fn join(op1: &Self, op2: &Self) -> Self {
+        let mut equalities = Equalities::new(&op1.escaping);
+        // Classes of the result are the intersections of the classes of `op1` and `op2`, and are
+        // represented by their smallest local
+        let mut classes = HashMap::new();
+        equalities.remainders = op1
+            .remainders
+            .iter()
+            .filter(|&(local, fact)| op2.remainders.get(local) == Some(fact))
+            .map(|(&local, &fact)| (local, fact))
+            .collect();
+        for (local, &r1) in op1.representative.iter_enumerated() {
+            let representative = *classes
+                .entry((r1, op2.representative[local]))
+                .or_insert(local);
+            equalities.representative[local] = representative;
+            if representative != local {
+                let members = &mut equalities.members[representative];
+                if members.is_empty() {
+                    members.push(representative);
+                }
+                members.push(local);
+            }
+        }
+        equalities
+    }
+
+    /// Equalities after the statement at `location`
+    pub fn apply_statement(&mut self, stmt: &Statement, location: Location) {
+        match stmt.kind {
+            StatementKind::Assign(ref place, ref rvalue) => {
+                let copy = match (place, &**rvalue) {
+                    (Place::Base(PlaceBase::Local(local)), Rvalue::Use(op)) => {
+                        operand_local(op).map(|source| (*local, source))
+                    }
+                    _ => None,
+                };
+                match copy {
+                    // `x = x` keeps the class of `x`
+                    Some((local, source)) if local == source => {}
+                    Some((local, source)) => {
+                        self.kill(local);
+                        self.union(local, source);
+                    }
+                    None => self.kill_place(place),
+                }
+                if let (
+                    Place::Base(PlaceBase::Local(local)),
+                    Rvalue::BinaryOp(BinOp::Rem, dividend, divisor),
+                ) = (place, &**rvalue)
+                {
+                    let (dividend, divisor) = (operand_local(dividend), operand_local(divisor));
+                    // `x = x % m` relates the new `x` to the old one, and locals that may be
+                    // written through a pointer can change without the fact being killed
+                    let kept = !self.escaping[*local]
+                        && [dividend, divisor]
+                            .iter()
+                            .flatten()
+                            .all(|&other| other != *local && !self.escaping[other]);
+                    if kept {
+                        self.remainders
+                            .insert(*local, (location, dividend, divisor));
+                    }
+                }
+            }
+            StatementKind::SetDiscriminant { ref place, .. } => self.kill_place(place),
+            StatementKind::InlineAsm(ref asm) => {
+                for place in asm.outputs.iter() {
+                    self.kill_place(place);
+                }
+            }
+            StatementKind::StorageLive(local) | StatementKind::StorageDead(local) => {
+                self.kill(local)
+            }
+            _ => {}
+        }
+    }
+
+    /// Equalities on the edge from a terminator to `target`
+    pub fn apply_terminator(&mut self, kind: &TerminatorKind, target: BasicBlock) {
+        match *kind {
+            TerminatorKind::Call {
+                destination: Some((ref place, dest)),
+                ..
+            } if dest == target => self.kill_place(place),
+            TerminatorKind::Drop { ref location, .. }
+            | TerminatorKind::DropAndReplace { ref location, .. } => self.kill_place(location),
+            _ => {}
+        }
+    }
+}
+
+impl PartialEq for Equalities {
+    fn eq(&self, other: &Self) -> bool {
+        self.smallest() == other.smallest() && self.remainders == other.remainders
+    }
+}
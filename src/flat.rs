@@ -0,0 +1,211 @@
+use crate::int::{ConstInt, IntType};
+use crate::lattice::{Arith, SimpleLattice};
+use rustc::mir::interpret::ConstValue;
+use rustc::mir::{BinOp, CastKind, UnOp};
+use rustc::ty::layout::Size;
+use rustc::ty::TyKind;
+
+/// Flat lattice over `T`: no value, exactly one value, or any value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flat<T> {
+    Bottom,
+    Value(T),
+    Top,
+}
+
+use Flat::*;
+
+impl<T: Clone + PartialEq> Flat<T> {
+    pub fn join(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Bottom, a) | (a, Bottom) => a.clone(),
+            (Value(a), Value(b)) if a == b => Value(a.clone()),
+            _ => Top,
+        }
+    }
+
+    pub fn meet(&self, other: &Self) -> Self {
+        match (self, other) {
+            (Top, a) | (a, Top) => a.clone(),
+            (Value(a), Value(b)) if a == b => Value(a.clone()),
+            _ => Bottom,
+        }
+    }
+
+    /// The value, if there is exactly one
+    pub fn value(&self) -> Option<&T> {
+        match self {
+            Value(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    /// Applies `f` to the value, keeping bottom and top
+    pub fn map<U, F: FnOnce(&T) -> U>(&self, f: F) -> Flat<U> {
+        match self {
+            Bottom => Bottom,
+            Value(a) => Value(f(a)),
+            Top => Top,
+        }
+    }
+}
+
+/// Constant propagation over integers, `bool` and `char`. Values are kept as they are read with
+/// the signedness of their type, so `u128` values above `i128::max_value()` are top.
+pub type ConstantPropagation = Flat<i128>;
+
+/// Result `result` of an arithmetic operation on `ty` (`None` if it overflows an `i128`, in which
+/// case `wrapped` is its value modulo 2^128). If the operation is `exact`, overflow panics.
+fn overflowing(result: Option<i128>, wrapped: i128, ty: IntType, exact: bool) -> Flat<i128> {
+    match result {
+        Some(r) if ty.contains(r) => Value(r),
+        // A `u128` value that doesn't fit in an `i128`
+        None if !ty.signed => Top,
+        _ if exact => Bottom,
+        Some(r) => ty.wrap(r).map_or(Top, Value),
+        None => Value(wrapped),
+    }
+}
+
+fn eval_binop(op: &BinOp, x: i128, y: i128, arith: &Arith) -> Flat<i128> {
+    let ty = match arith.ty {
+        Some(ty) => ty,
+        None => return Top,
+    };
+    let exact = arith.is_exact();
+    let bits = i128::from(ty.bits());
+    match op {
+        BinOp::Add => overflowing(x.checked_add(y), x.wrapping_add(y), ty, exact),
+        BinOp::Sub => overflowing(x.checked_sub(y), x.wrapping_sub(y), ty, exact),
+        BinOp::Mul => overflowing(x.checked_mul(y), x.wrapping_mul(y), ty, exact),
+        // Division by zero and `MIN / -1` panic even without overflow checks
+        BinOp::Div | BinOp::Rem if y == 0 || (ty.signed && x == ty.min() && y == -1) => Bottom,
+        BinOp::Div => overflowing(x.checked_div(y), 0, ty, true),
+        BinOp::Rem => overflowing(x.checked_rem(y), 0, ty, true),
+        BinOp::Shl | BinOp::Shr if exact && (y < 0 || y >= bits) => Bottom,
+        BinOp::Shl | BinOp::Shr => {
+            // Without overflow checks, the shift amount is masked
+            let y = (y & (bits - 1)) as u32;
+            match op {
+                BinOp::Shl => ty.wrap(x.wrapping_shl(y)).map_or(Top, Value),
+                // Values are sign extended for signed types and non-negative otherwise, so this is
+                // an arithmetic or logical shift as needed
+                _ => Value(x >> y),
+            }
+        }
+        BinOp::BitAnd => Value(x & y),
+        BinOp::BitOr => Value(x | y),
+        BinOp::BitXor => Value(x ^ y),
+        BinOp::Eq => Value((x == y) as i128),
+        BinOp::Ne => Value((x != y) as i128),
+        BinOp::Lt => Value((x < y) as i128),
+        BinOp::Le => Value((x <= y) as i128),
+        BinOp::Gt => Value((x > y) as i128),
+        BinOp::Ge => Value((x >= y) as i128),
+        BinOp::Offset => Top,
+    }
+}
+
+/// Whether a comparison holds
+fn compare(op: &BinOp, x: i128, y: i128) -> Option<bool> {
+    match op {
+        BinOp::Eq => Some(x == y),
+        BinOp::Ne => Some(x != y),
+        BinOp::Lt => Some(x < y),
+        BinOp::Le => Some(x <= y),
+        BinOp::Gt => Some(x > y),
+        BinOp::Ge => Some(x >= y),
+        _ => None,
+    }
+}
+
+impl SimpleLattice for Flat<i128> {
+    fn applies(ty: &TyKind) -> bool {
+        match ty {
+            TyKind::Int(_) | TyKind::Uint(_) | TyKind::Bool | TyKind::Char => true,
+            _ => false,
+        }
+    }
+
+    fn bot() -> Self {
+        Bottom
+    }
+
+    fn top() -> Self {
+        Top
+    }
+
+    fn join(op1: &Self, op2: &Self) -> Self {
+        op1.join(op2)
+    }
+
+    fn alpha(ty: &TyKind, size: Size, a: ConstValue) -> Self {
+        ConstInt::decode(ty, size, a)
+            .and_then(ConstInt::to_i128)
+            .map_or(Top, Value)
+    }
+
+    fn flow_binop(op: &BinOp, arg1: &Self, arg2: &Self, arith: &Arith) -> Self {
+        match (op, arg1, arg2) {
+            (_, Bottom, _) | (_, _, Bottom) => Bottom,
+            (_, Value(x), Value(y)) => eval_binop(op, *x, *y, arith),
+            // Zero absorbs whatever the other operand is
+            (BinOp::Mul, Value(0), _)
+            | (BinOp::Mul, _, Value(0))
+            | (BinOp::BitAnd, Value(0), _)
+            | (BinOp::BitAnd, _, Value(0)) => Value(0),
+            _ => Top,
+        }
+    }
+
+    fn flow_unop(op: &UnOp, arg: &Self, arith: &Arith) -> Self {
+        let x = match arg {
+            Value(x) => *x,
+            _ => return *arg,
+        };
+        let ty = match arith.ty {
+            Some(ty) => ty,
+            None => return Top,
+        };
+        match op {
+            UnOp::Neg => overflowing(x.checked_neg(), x.wrapping_neg(), ty, arith.is_exact()),
+            UnOp::Not if arith.boolean => Value(x ^ 1),
+            UnOp::Not => ty.wrap(!x).map_or(Top, Value),
+        }
+    }
+
+    fn flow_cond_true(op: &BinOp, arg1: &Self, arg2: &Self) -> (Self, Self) {
+        match (op, arg1, arg2) {
+            (BinOp::Eq, _, _) => {
+                let both = arg1.meet(arg2);
+                (both, both)
+            }
+            // The branch can't be taken if the comparison is known to fail
+            (_, Value(x), Value(y)) if compare(op, *x, *y) == Some(false) => (Bottom, Bottom),
+            _ => (*arg1, *arg2),
+        }
+    }
+
+    fn flow_cond_false(op: &BinOp, arg1: &Self, arg2: &Self) -> (Self, Self) {
+        match (arg1, arg2) {
+            (Value(x), Value(y)) if compare(op, *x, *y) == Some(true) => (Bottom, Bottom),
+            _ => (*arg1, *arg2),
+        }
+    }
+
+    fn flow_cast(kind: &CastKind, from_ty: &TyKind, to_ty: &TyKind, arg: &Self) -> Self {
+        let x = match (kind, arg) {
+            (_, Bottom) => return Bottom,
+            (CastKind::Misc, Value(x)) if Self::applies(from_ty) => *x,
+            _ => return Top,
+        };
+        // The width of `isize` and `usize` is unknown here, but only matters if the value doesn't
+        // fit in the narrowest one
+        let narrow = IntType::of(to_ty, Size::from_bits(16)).and_then(|ty| ty.wrap(x));
+        let wide = IntType::of(to_ty, Size::from_bits(128)).and_then(|ty| ty.wrap(x));
+        match (narrow, wide) {
+            (Some(narrow), Some(wide)) if narrow == wide => Value(narrow),
+            _ => Top,
+        }
+    }
+}
@@ -0,0 +1,691 @@
+use crate::cfg;
+use crate::config::Exceeded;
+use crate::lattice::{base_local, escaping};
+use crate::parallel::SharedMir;
+use crate::worklist::{Metrics, Strategy, Worklist};
+use rustc::mir::{
+    BasicBlock, Local, Location, Mir, Operand, Place, PlaceBase, ProjectionElem, Rvalue, Statement,
+    StatementKind, Terminator, TerminatorKind, RETURN_PLACE, START_BLOCK,
+};
+pub use rustc_data_structures::bit_set::BitSet;
+use rustc_data_structures::indexed_vec::{Idx, IndexVec};
+use std::time::{Duration, Instant};
+
+/// Direction in which information flows
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+/// How the information of several edges is combined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Confluence {
+    /// May analyses (e.g. liveness, reaching definitions)
+    Union,
+    /// Must analyses (e.g. available expressions)
+    Intersection,
+}
+
+/// An analysis whose transfer functions add (gen) and remove (kill) elements of a set. The effect
+/// of a whole block is computed once, so the fixpoint iteration only does set operations.
+pub trait GenKill: Sized {
+    type Idx: Idx + Send;
+
+    const DIRECTION: Direction;
+    const CONFLUENCE: Confluence;
+
+    fn new(mir: &Mir) -> Self;
+
+    /// Number of possible elements
+    fn domain_size(&self) -> usize;
+
+    /// Set at the start block (forward) or at blocks without successors (backward)
+    fn boundary(&self, _set: &mut BitSet<Self::Idx>) {}
+
+    fn statement_effect(
+        &self,
+        trans: &mut GenKillSet<Self::Idx>,
+        stmt: &Statement,
+        location: Location,
+    );
+
+    fn terminator_effect(
+        &self,
+        _trans: &mut GenKillSet<Self::Idx>,
+        _terminator: &Terminator,
+        _location: Location,
+    ) {
+    }
+
+    /// Effect of a call on the edge to its return block, the only one where `destination` is
+    /// written. It comes after `terminator_effect` for forward analyses, and before it for
+    /// backward ones.
+    fn call_return_effect(
+        &self,
+        _trans: &mut GenKillSet<Self::Idx>,
+        _destination: &Place,
+        _location: Location,
+    ) {
+    }
+}
+
+/// The composed effect of some statements. A later `gen` overrides an earlier `kill` of the same
+/// element and vice versa.
+#[derive(Debug, Clone)]
+pub struct GenKillSet<T: Idx> {
+    gen_set: BitSet<T>,
+    kill_set: BitSet<T>,
+}
+
+impl<T: Idx> GenKillSet<T> {
+    pub fn new(domain_size: usize) -> Self {
+        GenKillSet {
+            gen_set: BitSet::new_empty(domain_size),
+            kill_set: BitSet::new_empty(domain_size),
+        }
+    }
+
+    pub fn gen(&mut self, elem: T) {
+        self.gen_set.insert(elem);
+        self.kill_set.remove(elem);
+    }
+
+    pub fn kill(&mut self, elem: T) {
+        self.kill_set.insert(elem);
+        self.gen_set.remove(elem);
+    }
+
+    pub fn apply(&self, set: &mut BitSet<T>) {
+        set.subtract(&self.kill_set);
+        set.union(&self.gen_set);
+    }
+
+    fn clear(&mut self) {
+        self.gen_set.clear();
+        self.kill_set.clear();
+    }
+}
+
+/// Block a call returns to, and the effect of the edge to it
+type CallReturn<T> = (BasicBlock, GenKillSet<T>);
+
+/// Fixpoint computation of a gen/kill analysis over one function
+pub struct GenKillAnalysis<'tcx, A: GenKill> {
+    mir: SharedMir<'tcx>,
+    analysis: A,
+    /// Effect of each block, in the direction of the analysis
+    transfer: IndexVec<BasicBlock, GenKillSet<A::Idx>>,
+    /// Effect of the return edge of each block that ends with a call, and the block it returns to
+    call_return: IndexVec<BasicBlock, Option<CallReturn<A::Idx>>>,
+    /// Set at the start of each block in the direction of the analysis: at its entry for forward
+    /// analyses, at its exit for backward ones
+    sets: IndexVec<BasicBlock, BitSet<A::Idx>>,
+    order: Vec<BasicBlock>,
+    worklist: Worklist,
+}
+
+impl<'tcx, A: GenKill> GenKillAnalysis<'tcx, A> {
+    pub fn new(mir: &'tcx Mir<'tcx>, strategy: Strategy) -> Self {
+        let analysis = A::new(mir);
+        let size = analysis.domain_size();
+        let blocks = mir.basic_blocks();
+
+        let mut transfer = IndexVec::with_capacity(blocks.len());
+        let mut call_return = IndexVec::with_capacity(blocks.len());
+        for (block, data) in blocks.iter_enumerated() {
+            let mut trans = GenKillSet::new(size);
+            let terminator_location = mir.terminator_loc(block);
+            call_return.push(match data.terminator().kind {
+                TerminatorKind::Call {
+                    destination: Some((ref place, return_block)),
+                    ..
+                } => {
+                    let mut trans = GenKillSet::new(size);
+                    analysis.call_return_effect(&mut trans, place, terminator_location);
+                    Some((return_block, trans))
+                }
+                _ => None,
+            });
+            match A::DIRECTION {
+                Direction::Forward => {
+                    for (statement_index, stmt) in data.statements.iter().enumerate() {
+                        let location = Location {
+                            block,
+                            statement_index,
+                        };
+                        analysis.statement_effect(&mut trans, stmt, location);
+                    }
+                    analysis.terminator_effect(&mut trans, data.terminator(), terminator_location);
+                }
+                Direction::Backward => {
+                    analysis.terminator_effect(&mut trans, data.terminator(), terminator_location);
+                    for (statement_index, stmt) in data.statements.iter().enumerate().rev() {
+                        let location = Location {
+                            block,
+                            statement_index,
+                        };
+                        analysis.statement_effect(&mut trans, stmt, location);
+                    }
+                }
+            }
+            transfer.push(trans);
+        }
+
+        let initial = match A::CONFLUENCE {
+            Confluence::Union => BitSet::new_empty(size),
+            Confluence::Intersection => BitSet::new_filled(size),
+        };
+        let mut boundary = BitSet::new_empty(size);
+        analysis.boundary(&mut boundary);
+        let mut sets = IndexVec::from_elem_n(initial, blocks.len());
+        match A::DIRECTION {
+            Direction::Forward => sets[START_BLOCK] = boundary,
+            Direction::Backward => {
+                for (block, data) in blocks.iter_enumerated() {
+                    if data.terminator().successors().next().is_none() {
+                        sets[block] = boundary.clone();
+                    }
+                }
+            }
+        }
+
+        // Reverse postorder for forward analyses, postorder for backward ones
+        let mut order = cfg::reverse_postorder(blocks);
+        if A::DIRECTION == Direction::Backward {
+            order.reverse();
+        }
+        let mut worklist = Worklist::new(strategy, blocks.len());
+        for (priority, &block) in order.iter().enumerate() {
+            worklist.push(block, priority);
+        }
+        GenKillAnalysis {
+            mir: SharedMir(mir),
+            analysis,
+            transfer,
+            call_return,
+            sets,
+            order,
+            worklist,
+        }
+    }
+
+    pub fn run(&mut self) {
+        self.run_within(None, None);
+    }
+
+    /// Like `run`, but stops once the worklist has popped more than `max_iterations` blocks or
+    /// `timeout` has elapsed. The sets are then unsound until `give_up` is called.
+    pub fn run_within(
+        &mut self,
+        max_iterations: Option<usize>,
+        timeout: Option<Duration>,
+    ) -> Option<Exceeded> {
+        let started = Instant::now();
+        let mut priorities = IndexVec::from_elem_n(usize::max_value(), self.sets.len());
+        for (priority, &block) in self.order.iter().enumerate() {
+            priorities[block] = priority;
+        }
+        let predecessors = match A::DIRECTION {
+            Direction::Forward => None,
+            Direction::Backward => Some(cfg::predecessors(self.mir.basic_blocks())),
+        };
+        while let Some(block) = self.worklist.pop() {
+            if let Some(max) = max_iterations {
+                if self.worklist.metrics.iterations > max {
+                    return Some(Exceeded::Iterations(max));
+                }
+            }
+            if let Some(timeout) = timeout {
+                if started.elapsed() > timeout {
+                    return Some(Exceeded::Timeout(timeout));
+                }
+            }
+            let mut output = self.sets[block].clone();
+            self.transfer[block].apply(&mut output);
+            let neighbours = match predecessors {
+                Some(ref predecessors) => predecessors[block].clone(),
+                None => self.mir.basic_blocks()[block]
+                    .terminator()
+                    .successors()
+                    .cloned()
+                    .collect(),
+            };
+            for neighbour in neighbours {
+                let (source, target) = match A::DIRECTION {
+                    Direction::Forward => (block, neighbour),
+                    Direction::Backward => (neighbour, block),
+                };
+                // The destination of a call is only written on the edge to its return block
+                let mut on_return = None;
+                if let Some((return_block, ref trans)) = self.call_return[source] {
+                    if return_block == target {
+                        let mut set = output.clone();
+                        trans.apply(&mut set);
+                        on_return = Some(set);
+                    }
+                }
+                let edge_output = on_return.as_ref().unwrap_or(&output);
+                let changed = match A::CONFLUENCE {
+                    Confluence::Union => self.sets[neighbour].union(edge_output),
+                    Confluence::Intersection => self.sets[neighbour].intersect(edge_output),
+                };
+                if changed {
+                    self.worklist.push(neighbour, priorities[neighbour]);
+                }
+            }
+        }
+        None
+    }
+
+    /// Replaces every set with the one that holds whatever the fixpoint would have been: the full
+    /// set for unions, the empty one for intersections
+    pub fn give_up(&mut self) {
+        let size = self.analysis.domain_size();
+        for set in self.sets.iter_mut() {
+            *set = match A::CONFLUENCE {
+                Confluence::Union => BitSet::new_filled(size),
+                Confluence::Intersection => BitSet::new_empty(size),
+            };
+        }
+    }
+
+    pub fn metrics(&self) -> &Metrics {
+        &self.worklist.metrics
+    }
+
+    /// Set at the entry of `block` for forward analyses, at its exit for backward ones
+    pub fn set(&self, block: BasicBlock) -> &BitSet<A::Idx> {
+        &self.sets[block]
+    }
+
+    /// Calls `f` on each statement, in order, with the set that flows into its effect: the set
+    /// before it for forward analyses, and the set after it for backward ones
+    pub fn for_each_statement<F: FnMut(Location, &Statement<'tcx>, &BitSet<A::Idx>)>(
+        &self,
+        mut f: F,
+    ) {
+        let mut trans = GenKillSet::new(self.analysis.domain_size());
+        for (block, data) in self.mir.basic_blocks().iter_enumerated() {
+            let mut set = self.sets[block].clone();
+            match A::DIRECTION {
+                Direction::Forward => {
+                    for (statement_index, stmt) in data.statements.iter().enumerate() {
+                        let location = Location {
+                            block,
+                            statement_index,
+                        };
+                        f(location, stmt, &set);
+                        trans.clear();
+                        self.analysis.statement_effect(&mut trans, stmt, location);
+                        trans.apply(&mut set);
+                    }
+                }
+                Direction::Backward => {
+                    trans.clear();
+                    let terminator_location = self.mir.terminator_loc(block);
+                    self.analysis.terminator_effect(
+                        &mut trans,
+                        data.terminator(),
+                        terminator_location,
+                    );
+                    trans.apply(&mut set);
+                    let mut sets = Vec::with_capacity(data.statements.len());
+                    for (statement_index, stmt) in data.statements.iter().enumerate().rev() {
+                        sets.push(set.clone());
+                        trans.clear();
+                        let location = Location {
+                            block,
+                            statement_index,
+                        };
+                        self.analysis.statement_effect(&mut trans, stmt, location);
+                        trans.apply(&mut set);
+                    }
+                    for (statement_index, (stmt, set)) in
+                        data.statements.iter().zip(sets.iter().rev()).enumerate()
+                    {
+                        let location = Location {
+                            block,
+                            statement_index,
+                        };
+                        f(location, stmt, set);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Calls `f` on every local read by `place`: its base and the locals used as indices
+fn place_uses<F: FnMut(Local)>(place: &Place, f: &mut F) {
+    match place {
+        Place::Base(PlaceBase::Local(local)) => f(*local),
+        Place::Base(_) => {}
+        Place::Projection(proj) => {
+            if let ProjectionElem::Index(local) = proj.elem {
+                f(local);
+            }
+            place_uses(&proj.base, f);
+        }
+    }
+}
+
+fn operand_uses<F: FnMut(Local)>(operand: &Operand, f: &mut F) {
+    match operand {
+        Operand::Copy(place) | Operand::Move(place) => place_uses(place, f),
+        Operand::Constant(_) => {}
+    }
+}
+
+fn rvalue_uses<F: FnMut(Local)>(rvalue: &Rvalue, f: &mut F) {
+    match rvalue {
+        Rvalue::Use(op)
+        | Rvalue::Repeat(op, _)
+        | Rvalue::Cast(_, op, _)
+        | Rvalue::UnaryOp(_, op) => operand_uses(op, f),
+        Rvalue::Ref(_, _, place) | Rvalue::Len(place) | Rvalue::Discriminant(place) => {
+            place_uses(place, f)
+        }
+        Rvalue::BinaryOp(_, op1, op2) | Rvalue::CheckedBinaryOp(_, op1, op2) => {
+            operand_uses(op1, f);
+            operand_uses(op2, f);
+        }
+        Rvalue::Aggregate(_, ops) => {
+            for op in ops.iter() {
+                operand_uses(op, f);
+            }
+        }
+        Rvalue::NullaryOp(..) => {}
+    }
+}
+
+/// Locals whose value may be read later
+pub struct LiveLocals {
+    num_locals: usize,
+}
+
+impl GenKill for LiveLocals {
+    type Idx = Local;
+
+    const DIRECTION: Direction = Direction::Backward;
+    const CONFLUENCE: Confluence = Confluence::Union;
+
+    fn new(mir: &Mir) -> Self {
+        LiveLocals {
+            num_locals: mir.local_decls.len(),
+        }
+    }
+
+    fn domain_size(&self) -> usize {
+        self.num_locals
+    }
+
+    fn statement_effect(
+        &self,
+        trans: &mut GenKillSet<Local>,
+        stmt: &Statement,
+        _location: Location,
+    ) {
+        match stmt.kind {
+            StatementKind::Assign(ref place, ref rvalue) => {
+                match place {
+                    Place::Base(PlaceBase::Local(local)) => trans.kill(*local),
+                    // Only part of the local is overwritten
+                    _ => place_uses(place, &mut |local| trans.gen(local)),
+                }
+                rvalue_uses(rvalue, &mut |local| trans.gen(local));
+            }
+            StatementKind::SetDiscriminant { ref place, .. }
+            | StatementKind::FakeRead(_, ref place) => {
+                place_uses(place, &mut |local| trans.gen(local))
+            }
+            StatementKind::InlineAsm(ref asm) => {
+                for place in asm.outputs.iter() {
+                    place_uses(place, &mut |local| trans.gen(local));
+                }
+                for (_, op) in asm.inputs.iter() {
+                    operand_uses(op, &mut |local| trans.gen(local));
+                }
+            }
+            StatementKind::StorageLive(local) | StatementKind::StorageDead(local) => {
+                trans.kill(local)
+            }
+            _ => {}
+        }
+    }
+
+    fn terminator_effect(
+        &self,
+        trans: &mut GenKillSet<Local>,
+        terminator: &Terminator,
+        _location: Location,
+    ) {
+        match terminator.kind {
+            TerminatorKind::Call {
+                ref func, ref args, ..
+            } => {
+                operand_uses(func, &mut |local| trans.gen(local));
+                for arg in args.iter() {
+                    operand_uses(arg, &mut |local| trans.gen(local));
+                }
+            }
+            TerminatorKind::SwitchInt { ref discr, .. } => {
+                operand_uses(discr, &mut |local| trans.gen(local))
+            }
+            TerminatorKind::Assert { ref cond, .. } => {
+                operand_uses(cond, &mut |local| trans.gen(local))
+            }
+            TerminatorKind::Yield { ref value, .. } => {
+                operand_uses(value, &mut |local| trans.gen(local))
+            }
+            TerminatorKind::Drop { ref location, .. } => {
+                place_uses(location, &mut |local| trans.gen(local))
+            }
+            TerminatorKind::DropAndReplace {
+                ref location,
+                ref value,
+                ..
+            } => {
+                // The old value is dropped before the new one is written
+                place_uses(location, &mut |local| trans.gen(local));
+                operand_uses(value, &mut |local| trans.gen(local));
+            }
+            TerminatorKind::Return => trans.gen(RETURN_PLACE),
+            _ => {}
+        }
+    }
+
+    fn call_return_effect(
+        &self,
+        trans: &mut GenKillSet<Local>,
+        destination: &Place,
+        _location: Location,
+    ) {
+        if let Place::Base(PlaceBase::Local(local)) = destination {
+            trans.kill(*local);
+        }
+    }
+}
+
+/// A write to (part of) a local
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Definition {
+    pub location: Location,
+    pub local: Local,
+    /// Whether the whole local is overwritten
+    pub complete: bool,
+}
+
+/// Definitions that may reach a point without being overwritten. Elements are indices into
+/// `definitions`. Arguments are not considered to be defined anywhere. A write through a pointer
+/// is a partial definition of every local that may be borrowed mutably (see `escaping`), but
+/// writes a callee does through its arguments aren't considered.
+pub struct ReachingDefinitions {
+    pub definitions: Vec<Definition>,
+    by_local: IndexVec<Local, Vec<usize>>,
+    escaping: IndexVec<Local, bool>,
+}
+
+impl ReachingDefinitions {
+    fn define(&self, trans: &mut GenKillSet<usize>, location: Location, place: &Place) {
+        if is_indirect(place) {
+            for local in self.escaping.indices().filter(|&l| self.escaping[l]) {
+                trans.gen(self.definition(local, location));
+            }
+            return;
+        }
+        let local = match base_local(place) {
+            Some(local) => local,
+            None => return,
+        };
+        let index = self.definition(local, location);
+        if self.definitions[index].complete {
+            self.undefine(trans, local);
+        }
+        trans.gen(index);
+    }
+
+    fn definition(&self, local: Local, location: Location) -> usize {
+        self.by_local[local]
+            .iter()
+            .cloned()
+            .find(|&d| self.definitions[d].location == location)
+            .unwrap()
+    }
+
+    fn undefine(&self, trans: &mut GenKillSet<usize>, local: Local) {
+        for &d in self.by_local[local].iter() {
+            trans.kill(d);
+        }
+    }
+}
+
+/// Whether writing to `place` writes through a pointer (e.g. `(*_1).0`)
+fn is_indirect(place: &Place) -> bool {
+    match place {
+        Place::Base(_) => false,
+        Place::Projection(proj) => proj.elem == ProjectionElem::Deref || is_indirect(&proj.base),
+    }
+}
+
+/// Places written by a statement or terminator
+fn defined_places<'a, 'tcx>(
+    stmt: Option<&'a Statement<'tcx>>,
+    terminator: Option<&'a Terminator<'tcx>>,
+) -> Vec<&'a Place<'tcx>> {
+    if let Some(stmt) = stmt {
+        return match stmt.kind {
+            StatementKind::Assign(ref place, _)
+            | StatementKind::SetDiscriminant { ref place, .. } => {
+                vec![place]
+            }
+            StatementKind::InlineAsm(ref asm) => asm.outputs.iter().collect(),
+            _ => vec![],
+        };
+    }
+    match terminator.map(|t| &t.kind) {
+        Some(TerminatorKind::Call {
+            destination: Some((ref place, _)),
+            ..
+        })
+        | Some(TerminatorKind::DropAndReplace {
+            location: ref place,
+            ..
+        }) => vec![place],
+        _ => vec![],
+    }
+}
+
+impl GenKill for ReachingDefinitions {
+    type Idx = usize;
+
+    const DIRECTION: Direction = Direction::Forward;
+    const CONFLUENCE: Confluence = Confluence::Union;
+
+    fn new(mir: &Mir) -> Self {
+        let escaping = escaping(mir);
+        let mut definitions = Vec::new();
+        let mut by_local = IndexVec::from_elem_n(Vec::new(), mir.local_decls.len());
+        for (block, data) in mir.basic_blocks().iter_enumerated() {
+            let statements = data.statements.iter().map(Some);
+            for (statement_index, stmt) in statements.chain(std::iter::once(None)).enumerate() {
+                let terminator = if stmt.is_none() {
+                    Some(data.terminator())
+                } else {
+                    None
+                };
+                let location = Location {
+                    block,
+                    statement_index,
+                };
+                for place in defined_places(stmt, terminator) {
+                    let written: Vec<_> = if is_indirect(place) {
+                        escaping.indices().filter(|&l| escaping[l]).collect()
+                    } else {
+                        base_local(place).into_iter().collect()
+                    };
+                    for local in written {
+                        by_local[local].push(definitions.len());
+                        definitions.push(Definition {
+                            location,
+                            local,
+                            complete: place == &Place::Base(PlaceBase::Local(local)),
+                        });
+                    }
+                }
+            }
+        }
+        ReachingDefinitions {
+            definitions,
+            by_local,
+            escaping,
+        }
+    }
+
+    fn domain_size(&self) -> usize {
+        self.definitions.len()
+    }
+
+    fn statement_effect(
+        &self,
+        trans: &mut GenKillSet<usize>,
+        stmt: &Statement,
+        location: Location,
+    ) {
+        match stmt.kind {
+            StatementKind::StorageLive(local) | StatementKind::StorageDead(local) => {
+                self.undefine(trans, local)
+            }
+            _ => {
+                for place in defined_places(Some(stmt), None) {
+                    self.define(trans, location, place);
+                }
+            }
+        }
+    }
+
+    fn terminator_effect(
+        &self,
+        trans: &mut GenKillSet<usize>,
+        terminator: &Terminator,
+        location: Location,
+    ) {
+        // The destination of a call is only written on its return edge
+        if let TerminatorKind::DropAndReplace {
+            location: ref place,
+            ..
+        } = terminator.kind
+        {
+            self.define(trans, location, place);
+        }
+    }
+
+    fn call_return_effect(
+        &self,
+        trans: &mut GenKillSet<usize>,
+        destination: &Place,
+        location: Location,
+    ) {
+        self.define(trans, location, destination);
+    }
+}
@@ -0,0 +1,126 @@
+use rustc::mir::interpret::ConstValue;
+use rustc::ty::layout::Size;
+use rustc::ty::TyKind;
+
+/// Width and signedness of an integer type. `bool` and `char` are treated as unsigned integers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntType {
+    pub size: Size,
+    pub signed: bool,
+}
+
+impl IntType {
+    /// `pointer_size` is the width of `isize` and `usize` on the target.
+    pub fn of(ty: &TyKind, pointer_size: Size) -> Option<Self> {
+        let (bits, signed) = match ty {
+            TyKind::Bool => (8, false),
+            TyKind::Char => (32, false),
+            TyKind::Int(int_ty) => (
+                int_ty
+                    .bit_width()
+                    .map_or(pointer_size.bits(), |width| width as u64),
+                true,
+            ),
+            TyKind::Uint(uint_ty) => (
+                uint_ty
+                    .bit_width()
+                    .map_or(pointer_size.bits(), |width| width as u64),
+                false,
+            ),
+            _ => return None,
+        };
+        Some(IntType {
+            size: Size::from_bits(bits),
+            signed,
+        })
+    }
+
+    pub fn bits(&self) -> u64 {
+        self.size.bits()
+    }
+
+    /// Smallest value of the type
+    pub fn min(&self) -> i128 {
+        if !self.signed {
+            0
+        } else if self.bits() >= 128 {
+            i128::min_value()
+        } else {
+            -(1 << (self.bits() - 1))
+        }
+    }
+
+    /// Largest value of the type. This saturates at `i128::max_value()` for `u128`.
+    pub fn max(&self) -> i128 {
+        let bits = if self.signed {
+            self.bits() - 1
+        } else {
+            self.bits()
+        };
+        if bits >= 127 {
+            i128::max_value()
+        } else {
+            (1 << bits) - 1
+        }
+    }
+
+    pub fn contains(&self, x: i128) -> bool {
+        self.min() <= x && x <= self.max()
+    }
+
+    /// Value of `x` after being truncated to the width of the type (i.e. two's complement wrapping).
+    /// Returns `None` for `u128` values that don't fit in an `i128`.
+    pub fn wrap(&self, x: i128) -> Option<i128> {
+        let bits = self.bits();
+        if bits >= 128 {
+            return if self.signed || x >= 0 { Some(x) } else { None };
+        }
+        let shift = 128 - bits as u32;
+        if self.signed {
+            Some((x << shift) >> shift)
+        } else {
+            Some(((x as u128) << shift >> shift) as i128)
+        }
+    }
+}
+
+/// An integer constant, decoded according to the signedness of its type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConstInt {
+    Signed(i128),
+    Unsigned(u128),
+}
+
+impl ConstInt {
+    /// Decodes a constant of type `ty` and size `size`. Returns `None` if it is not an integer
+    /// (or `bool`/`char`) scalar.
+    pub fn decode(ty: &TyKind, size: Size, a: ConstValue) -> Option<Self> {
+        let signed = match ty {
+            TyKind::Int(_) => true,
+            TyKind::Uint(_) | TyKind::Bool | TyKind::Char => false,
+            _ => return None,
+        };
+        if size.bits() == 0 {
+            return None;
+        }
+        let bits = match a {
+            ConstValue::Scalar(scalar) => scalar.to_bits(size).ok()?,
+            _ => return None,
+        };
+        if signed {
+            // Sign extend from the width of the type
+            let shift = 128 - size.bits() as u32;
+            Some(ConstInt::Signed(((bits << shift) as i128) >> shift))
+        } else {
+            Some(ConstInt::Unsigned(bits))
+        }
+    }
+
+    pub fn to_i128(self) -> Option<i128> {
+        match self {
+            ConstInt::Signed(x) => Some(x),
+            ConstInt::Unsigned(x) if x <= i128::max_value() as u128 => Some(x as i128),
+            ConstInt::Unsigned(_) => None,
+        }
+    }
+}
@@ -0,0 +1,497 @@
+use crate::int::{ConstInt, IntType};
+use crate::lattice::{Arith, SimpleLattice, WideningHints};
+use rustc::mir::interpret::ConstValue;
+use rustc::mir::{BinOp, CastKind, Place, UnOp};
+use rustc::ty::layout::Size;
+use rustc::ty::TyKind;
+
+const NEG_INF: i128 = i128::min_value();
+const POS_INF: i128 = i128::max_value();
+
+/// Range of values of an integer. The transfer functions use the type of the operands to wrap
+/// around or to clamp their results, depending on the overflow semantics.
+///
+/// Bounds are `i128`s, and `i128::min_value()` and `i128::max_value()` stand for unbounded. In
+/// particular, `u128` values above `i128::max_value()` are only represented by an unbounded upper
+/// bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    Bottom,
+    /// All values from the first bound to the second one, inclusive
+    Range(i128, i128),
+}
+
+use Interval::*;
+
+impl Interval {
+    /// `[lo, hi]`, or bottom if it is empty
+    pub fn new(lo: i128, hi: i128) -> Self {
+        if lo <= hi {
+            Range(lo, hi)
+        } else {
+            Bottom
+        }
+    }
+
+    pub fn constant(x: i128) -> Self {
+        Range(x, x)
+    }
+
+    /// All the values of a type
+    pub fn of_type(ty: IntType) -> Self {
+        Range(ty.min(), ty.max())
+    }
+
+    pub fn contains(&self, x: i128) -> bool {
+        match *self {
+            Range(lo, hi) => lo <= x && x <= hi,
+            Bottom => false,
+        }
+    }
+
+    pub fn meet(&self, other: &Self) -> Self {
+        match (*self, *other) {
+            (Range(lo1, hi1), Range(lo2, hi2)) => Interval::new(lo1.max(lo2), hi1.min(hi2)),
+            _ => Bottom,
+        }
+    }
+
+    /// The value if there is only one
+    pub fn as_constant(&self) -> Option<i128> {
+        match *self {
+            Range(lo, hi) if lo == hi && lo != NEG_INF && hi != POS_INF => Some(lo),
+            _ => None,
+        }
+    }
+}
+
+fn is_inf(x: i128) -> bool {
+    x == NEG_INF || x == POS_INF
+}
+
+fn neg_bound(x: i128) -> i128 {
+    match x {
+        NEG_INF => POS_INF,
+        POS_INF => NEG_INF,
+        _ => -x,
+    }
+}
+
+/// `a + b`, where infinite bounds absorb finite ones. `up` decides `-inf + inf`.
+fn add_bound(a: i128, b: i128, up: bool) -> i128 {
+    let neg = a == NEG_INF || b == NEG_INF;
+    let pos = a == POS_INF || b == POS_INF;
+    match (neg, pos) {
+        (true, true) if up => POS_INF,
+        (true, _) => NEG_INF,
+        (_, true) => POS_INF,
+        // Saturation gives the infinity of the right sign
+        _ => a.saturating_add(b),
+    }
+}
+
+fn sub_bound(a: i128, b: i128, up: bool) -> i128 {
+    add_bound(a, neg_bound(b), up)
+}
+
+fn mul_bound(a: i128, b: i128) -> i128 {
+    if a == 0 || b == 0 {
+        0
+    } else if is_inf(a) || is_inf(b) {
+        if (a < 0) == (b < 0) {
+            POS_INF
+        } else {
+            NEG_INF
+        }
+    } else {
+        a.saturating_mul(b)
+    }
+}
+
+/// `a / b` rounded towards zero, for `b != 0`
+fn div_bound(a: i128, b: i128) -> i128 {
+    if is_inf(a) {
+        if (a < 0) == (b < 0) {
+            POS_INF
+        } else {
+            NEG_INF
+        }
+    } else if is_inf(b) {
+        0
+    } else {
+        a.checked_div(b).unwrap_or(POS_INF)
+    }
+}
+
+/// Smallest and largest of some bounds
+fn hull(bounds: &[i128]) -> (i128, i128) {
+    let lo = *bounds.iter().min().unwrap();
+    let hi = *bounds.iter().max().unwrap();
+    (lo, hi)
+}
+
+/// `2^k`, saturated
+fn pow2(k: i128) -> i128 {
+    if k >= 127 {
+        POS_INF
+    } else {
+        1 << k
+    }
+}
+
+/// Smallest `2^k - 1` that is at least `x`, for `x >= 0`
+fn all_ones(x: i128) -> i128 {
+    let mut mask = 0i128;
+    while mask < x && mask != POS_INF {
+        mask = (mask << 1) | 1;
+    }
+    mask
+}
+
+/// The mathematical result `[lo, hi]` of an operation on values of type `ty`, after overflow.
+/// If the operation is `exact`, values outside of the type can't be produced (the program panics
+/// instead), so they are dropped. Otherwise they wrap around.
+fn fit(lo: i128, hi: i128, ty: Option<IntType>, exact: bool) -> Interval {
+    let ty = match ty {
+        Some(ty) => ty,
+        None => return Interval::new(lo, hi),
+    };
+    if exact {
+        return Interval::new(lo.max(ty.min()), hi.min(ty.max()));
+    }
+    if lo > hi {
+        return Bottom;
+    }
+    // An infinite bound of a 128-bit type may have saturated, or stand for values that could
+    // overflow, so it can hide a wraparound
+    if ty.bits() >= 128 && (is_inf(lo) || is_inf(hi)) {
+        return Interval::of_type(ty);
+    }
+    if ty.contains(lo) && ty.contains(hi) {
+        return Range(lo, hi);
+    }
+    // The range stays contiguous if all of it wraps around by the same amount
+    if ty.bits() < 128 && !is_inf(lo) && !is_inf(hi) {
+        if let Some(width) = hi.checked_sub(lo) {
+            if width < 1 << ty.bits() {
+                if let (Some(lo), Some(hi)) = (ty.wrap(lo), ty.wrap(hi)) {
+                    if lo <= hi {
+                        return Range(lo, hi);
+                    }
+                }
+            }
+        }
+    }
+    Interval::of_type(ty)
+}
+
+/// Values of the type, or everything if it is unknown
+fn full(ty: Option<IntType>) -> Interval {
+    ty.map_or(Interval::top(), Interval::of_type)
+}
+
+/// Parts of a divisor without zero
+fn split_at_zero(lo: i128, hi: i128) -> Vec<(i128, i128)> {
+    let mut parts = Vec::with_capacity(2);
+    if lo < 0 {
+        parts.push((lo, hi.min(-1)));
+    }
+    if hi > 0 {
+        parts.push((lo.max(1), hi));
+    }
+    parts
+}
+
+fn div(lo1: i128, hi1: i128, lo2: i128, hi2: i128) -> Interval {
+    // Dividing by zero panics, so only the other divisors matter
+    let mut result = Bottom;
+    for (lo2, hi2) in split_at_zero(lo2, hi2) {
+        let (lo, hi) = hull(&[
+            div_bound(lo1, lo2),
+            div_bound(lo1, hi2),
+            div_bound(hi1, lo2),
+            div_bound(hi1, hi2),
+        ]);
+        result = Interval::join(&result, &Range(lo, hi));
+    }
+    result
+}
+
+fn rem(lo1: i128, hi1: i128, lo2: i128, hi2: i128) -> Interval {
+    let parts = split_at_zero(lo2, hi2);
+    if parts.is_empty() {
+        return Bottom;
+    }
+    // The remainder is smaller than the divisor in absolute value and has the sign of the
+    // dividend
+    let smallest = parts
+        .iter()
+        .map(|&(lo, hi)| if lo > 0 { lo } else { neg_bound(hi) })
+        .min()
+        .unwrap();
+    if lo1 >= 0 && hi1 < smallest {
+        return Range(lo1, hi1);
+    }
+    let largest = parts
+        .iter()
+        .map(|&(lo, hi)| if lo > 0 { hi } else { neg_bound(lo) })
+        .max()
+        .unwrap();
+    let max = if largest == POS_INF {
+        POS_INF
+    } else {
+        largest - 1
+    };
+    let lo = if lo1 >= 0 { 0 } else { lo1.max(neg_bound(max)) };
+    let hi = if hi1 <= 0 { 0 } else { hi1.min(max) };
+    Range(lo, hi)
+}
+
+fn shift(op: &BinOp, lo1: i128, hi1: i128, lo2: i128, hi2: i128, arith: &Arith) -> Interval {
+    let bits = match arith.ty {
+        Some(ty) => i128::from(ty.bits()),
+        None => return Interval::top(),
+    };
+    // Other shift amounts panic with overflow checks and are masked without them
+    if lo2 < 0 || hi2 >= bits {
+        return full(arith.ty);
+    }
+    match op {
+        BinOp::Shr => {
+            // Shifting right moves values towards 0 (or -1). An unbounded value stays unbounded,
+            // since it may be a `u128` above `POS_INF`.
+            let (lo, hi) = hull(&[lo1 >> lo2, lo1 >> hi2, hi1 >> lo2, hi1 >> hi2]);
+            Range(lo, if hi1 == POS_INF { POS_INF } else { hi })
+        }
+        _ => {
+            // Bits shifted out are lost even with overflow checks
+            let (lo, hi) = hull(&[
+                mul_bound(lo1, pow2(lo2)),
+                mul_bound(lo1, pow2(hi2)),
+                mul_bound(hi1, pow2(lo2)),
+                mul_bound(hi1, pow2(hi2)),
+            ]);
+            fit(lo, hi, arith.ty, false)
+        }
+    }
+}
+
+fn bit_op(op: &BinOp, lo1: i128, hi1: i128, lo2: i128, hi2: i128, arith: &Arith) -> Interval {
+    match op {
+        // The result has no more bits than a non-negative operand
+        BinOp::BitAnd if lo1 >= 0 && lo2 >= 0 => Range(0, hi1.min(hi2)),
+        BinOp::BitAnd if lo1 >= 0 => Range(0, hi1),
+        BinOp::BitAnd if lo2 >= 0 => Range(0, hi2),
+        BinOp::BitOr if lo1 >= 0 && lo2 >= 0 => Range(lo1.max(lo2), all_ones(hi1.max(hi2))),
+        BinOp::BitXor if lo1 >= 0 && lo2 >= 0 => Range(0, all_ones(hi1.max(hi2))),
+        _ => full(arith.ty),
+    }
+}
+
+impl SimpleLattice for Interval {
+    fn applies(ty: &TyKind) -> bool {
+        match ty {
+            TyKind::Int(_) | TyKind::Uint(_) => true,
+            _ => false,
+        }
+    }
+
+    fn bot() -> Self {
+        Bottom
+    }
+
+    fn top() -> Self {
+        Range(NEG_INF, POS_INF)
+    }
+
+    fn join(op1: &Self, op2: &Self) -> Self {
+        match (*op1, *op2) {
+            (Bottom, a) | (a, Bottom) => a,
+            (Range(lo1, hi1), Range(lo2, hi2)) => Range(lo1.min(lo2), hi1.max(hi2)),
+        }
+    }
+
+    fn alpha(ty: &TyKind, size: Size, a: ConstValue) -> Self {
+        match ConstInt::decode(ty, size, a) {
+            Some(ConstInt::Unsigned(x)) if x > POS_INF as u128 => Range(POS_INF, POS_INF),
+            Some(x) => Interval::constant(x.to_i128().unwrap()),
+            None => Interval::top(),
+        }
+    }
+
+    fn flow_binop(op: &BinOp, arg1: &Self, arg2: &Self, arith: &Arith) -> Self {
+        // Operands are values of their type even if nothing else is known about them. The amount
+        // of a shift may have another type.
+        let arg1 = arg1.meet(&full(arith.ty));
+        let arg2 = match op {
+            BinOp::Shl | BinOp::Shr => *arg2,
+            _ => arg2.meet(&full(arith.ty)),
+        };
+        let (lo1, hi1, lo2, hi2) = match (arg1, arg2) {
+            (Range(lo1, hi1), Range(lo2, hi2)) => (lo1, hi1, lo2, hi2),
+            _ => return Bottom,
+        };
+        match op {
+            BinOp::Add => fit(
+                add_bound(lo1, lo2, false),
+                add_bound(hi1, hi2, true),
+                arith.ty,
+                arith.is_exact(),
+            ),
+            BinOp::Sub => fit(
+                sub_bound(lo1, hi2, false),
+                sub_bound(hi1, lo2, true),
+                arith.ty,
+                arith.is_exact(),
+            ),
+            BinOp::Mul => {
+                let (lo, hi) = hull(&[
+                    mul_bound(lo1, lo2),
+                    mul_bound(lo1, hi2),
+                    mul_bound(hi1, lo2),
+                    mul_bound(hi1, hi2),
+                ]);
+                fit(lo, hi, arith.ty, arith.is_exact())
+            }
+            // Division overflow (`MIN / -1`) always panics
+            BinOp::Div => match div(lo1, hi1, lo2, hi2) {
+                Range(lo, hi) => fit(lo, hi, arith.ty, true),
+                Bottom => Bottom,
+            },
+            BinOp::Rem => rem(lo1, hi1, lo2, hi2),
+            BinOp::Shl | BinOp::Shr => shift(op, lo1, hi1, lo2, hi2, arith),
+            BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor => bit_op(op, lo1, hi1, lo2, hi2, arith),
+            // Comparisons give booleans, and offsets pointers
+            _ => Interval::top(),
+        }
+    }
+
+    fn flow_unop(op: &UnOp, arg: &Self, arith: &Arith) -> Self {
+        let (lo, hi) = match arg.meet(&full(arith.ty)) {
+            Range(lo, hi) => (lo, hi),
+            Bottom => return Bottom,
+        };
+        match op {
+            UnOp::Neg => fit(neg_bound(hi), neg_bound(lo), arith.ty, arith.is_exact()),
+            UnOp::Not => match arith.ty {
+                // `!x` is `-x - 1` for signed integers and `MAX - x` for unsigned ones
+                Some(ty) if ty.signed && !is_inf(lo) && !is_inf(hi) => Range(!hi, !lo),
+                Some(ty) if !ty.signed && ty.bits() < 128 && !is_inf(hi) => {
+                    Range(ty.max() - hi, ty.max() - lo)
+                }
+                _ => full(arith.ty),
+            },
+        }
+    }
+
+    fn flow_cond_true(op: &BinOp, arg1: &Self, arg2: &Self) -> (Self, Self) {
+        let (lo1, hi1, lo2, hi2) = match (*arg1, *arg2) {
+            (Range(lo1, hi1), Range(lo2, hi2)) => (lo1, hi1, lo2, hi2),
+            _ => return (*arg1, *arg2),
+        };
+        match op {
+            BinOp::Eq => {
+                let both = arg1.meet(arg2);
+                (both, both)
+            }
+            // Only a bound equal to the single value of the other side can be removed
+            BinOp::Ne => match (arg1.as_constant(), arg2.as_constant()) {
+                (_, Some(c)) => (remove_bound(lo1, hi1, c), *arg2),
+                (Some(c), _) => (*arg1, remove_bound(lo2, hi2, c)),
+                _ => (*arg1, *arg2),
+            },
+            BinOp::Lt => (
+                Interval::new(lo1, hi1.min(sub_bound(hi2, 1, true))),
+                Interval::new(lo2.max(add_bound(lo1, 1, false)), hi2),
+            ),
+            BinOp::Le => (
+                Interval::new(lo1, hi1.min(hi2)),
+                Interval::new(lo2.max(lo1), hi2),
+            ),
+            BinOp::Gt => {
+                let (b, a) = Self::flow_cond_true(&BinOp::Lt, arg2, arg1);
+                (a, b)
+            }
+            BinOp::Ge => {
+                let (b, a) = Self::flow_cond_true(&BinOp::Le, arg2, arg1);
+                (a, b)
+            }
+            _ => (*arg1, *arg2),
+        }
+    }
+
+    fn flow_cond_false(op: &BinOp, arg1: &Self, arg2: &Self) -> (Self, Self) {
+        let negated = match op {
+            BinOp::Eq => BinOp::Ne,
+            BinOp::Ne => BinOp::Eq,
+            BinOp::Lt => BinOp::Ge,
+            BinOp::Le => BinOp::Gt,
+            BinOp::Gt => BinOp::Le,
+            BinOp::Ge => BinOp::Lt,
+            _ => return (*arg1, *arg2),
+        };
+        Self::flow_cond_true(&negated, arg1, arg2)
+    }
+
+    fn flow_cast(kind: &CastKind, from_ty: &TyKind, to_ty: &TyKind, arg: &Self) -> Self {
+        let (lo, hi) = match (kind, *arg) {
+            (_, Bottom) => return Bottom,
+            (CastKind::Misc, Range(lo, hi)) => (lo, hi),
+            _ => return Interval::top(),
+        };
+        if !Self::applies(from_ty) {
+            return Interval::top();
+        }
+        // `isize` and `usize` are at least 16 bits wide
+        let from = IntType::of(from_ty, Size::from_bits(16));
+        // An unbounded `u128` may be above `i128::max_value()`
+        if hi == POS_INF && from.map_or(true, |from| !from.signed && from.bits() == 128) {
+            return Interval::top();
+        }
+        match IntType::of(to_ty, Size::from_bits(16)) {
+            Some(to) if to.contains(lo) && to.contains(hi) => Range(lo, hi),
+            _ => Interval::top(),
+        }
+    }
+
+    fn flow_len(_place: &Place) -> Self {
+        Range(0, POS_INF)
+    }
+
+    fn widen(op1: &Self, op2: &Self, hints: &WideningHints) -> Self {
+        match (*op1, *op2) {
+            (Range(lo1, hi1), Range(lo2, hi2)) => {
+                let (min, max) = hints
+                    .ty
+                    .map_or((NEG_INF, POS_INF), |ty| (ty.min(), ty.max()));
+                // Stop at the closest constant of the program that still bounds the new value
+                let thresholds = hints.thresholds;
+                let lo = if lo2 < lo1 {
+                    let below = thresholds.iter().rev().find(|&&t| t <= lo2 && t >= min);
+                    below.map_or(min.min(lo2), |&t| t)
+                } else {
+                    lo1
+                };
+                let hi = if hi2 > hi1 {
+                    let above = thresholds.iter().find(|&&t| t >= hi2 && t <= max);
+                    above.map_or(max.max(hi2), |&t| t)
+                } else {
+                    hi1
+                };
+                Range(lo, hi)
+            }
+            _ => Self::join(op1, op2),
+        }
+    }
+}
+
+/// `[lo, hi]` without `c`, which can only make a difference at the bounds
+fn remove_bound(lo: i128, hi: i128, c: i128) -> Interval {
+    if c == lo && !is_inf(lo) {
+        Interval::new(lo + 1, hi)
+    } else if c == hi && !is_inf(hi) {
+        Interval::new(lo, hi - 1)
+    } else {
+        Range(lo, hi)
+    }
+}
@@ -0,0 +1,353 @@
+use crate::int::{ConstInt, IntType};
+use crate::lattice::{Arith, SimpleLattice};
+use rustc::mir::interpret::ConstValue;
+use rustc::mir::{BinOp, CastKind, UnOp};
+use rustc::ty::layout::Size;
+use rustc::ty::TyKind;
+
+/// Bits of an integer that are known (a tristate number). Values are seen as 128 bits wide, sign
+/// extended for signed types and zero extended for unsigned ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KnownBits {
+    Bottom,
+    /// Bits set in `mask` are unknown, the others are those of `value`. Unknown bits of `value`
+    /// are 0.
+    Bits {
+        value: u128,
+        mask: u128,
+    },
+}
+
+use KnownBits::*;
+
+impl KnownBits {
+    pub fn new(value: u128, mask: u128) -> Self {
+        Bits {
+            value: value & !mask,
+            mask,
+        }
+    }
+
+    pub fn constant(x: u128) -> Self {
+        KnownBits::new(x, 0)
+    }
+
+    /// The value if all bits are known
+    pub fn as_constant(&self) -> Option<u128> {
+        match *self {
+            Bits { value, mask: 0 } => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Whether the bits of `bits` are 0 in all values, e.g. for a pointer aligned to 8 bytes and
+    /// `bits == 7`
+    pub fn known_zero(&self, bits: u128) -> bool {
+        match *self {
+            Bits { value, mask } => (value | mask) & bits == 0,
+            Bottom => true,
+        }
+    }
+
+    /// Whether the bits of `bits` are 1 in all values, e.g. for a flag that is always set
+    pub fn known_one(&self, bits: u128) -> bool {
+        match *self {
+            Bits { value, .. } => value & bits == bits,
+            Bottom => true,
+        }
+    }
+
+    pub fn contains(&self, x: u128) -> bool {
+        match *self {
+            Bits { value, mask } => (x ^ value) & !mask == 0,
+            Bottom => false,
+        }
+    }
+
+    /// Values with the bits known by both sides
+    pub fn meet(&self, other: &Self) -> Self {
+        match (*self, *other) {
+            (
+                Bits {
+                    value: v1,
+                    mask: m1,
+                },
+                Bits {
+                    value: v2,
+                    mask: m2,
+                },
+            ) if (v1 ^ v2) & !m1 & !m2 == 0 => KnownBits::new(v1 | v2, m1 & m2),
+            _ => Bottom,
+        }
+    }
+}
+
+// The operations on the bits below are those of tristate numbers (as in the Linux BPF verifier).
+// They compute modulo 2^128, and `fit` then brings the result to the width of its type.
+
+fn add(v1: u128, m1: u128, v2: u128, m2: u128) -> (u128, u128) {
+    let sum_mask = m1.wrapping_add(m2);
+    let sum_value = v1.wrapping_add(v2);
+    // Bits where the carries may differ
+    let carries = sum_mask.wrapping_add(sum_value) ^ sum_value;
+    let mask = carries | m1 | m2;
+    (sum_value & !mask, mask)
+}
+
+fn sub(v1: u128, m1: u128, v2: u128, m2: u128) -> (u128, u128) {
+    let diff = v1.wrapping_sub(v2);
+    let borrows = diff.wrapping_add(m1) ^ diff.wrapping_sub(m2);
+    let mask = borrows | m1 | m2;
+    (diff & !mask, mask)
+}
+
+fn mul(mut v1: u128, mut m1: u128, mut v2: u128, mut m2: u128) -> (u128, u128) {
+    let product = v1.wrapping_mul(v2);
+    // Sum of the partial products with unknown bits, one bit of the first operand at a time
+    let (mut acc_value, mut acc_mask) = (0, 0);
+    while v1 != 0 || m1 != 0 {
+        let partial = if v1 & 1 != 0 {
+            Some(m2)
+        } else if m1 & 1 != 0 {
+            Some(v2 | m2)
+        } else {
+            None
+        };
+        if let Some(partial) = partial {
+            let (v, m) = add(acc_value, acc_mask, 0, partial);
+            acc_value = v;
+            acc_mask = m;
+        }
+        v1 >>= 1;
+        m1 >>= 1;
+        v2 <<= 1;
+        m2 <<= 1;
+    }
+    add(product, 0, acc_value, acc_mask)
+}
+
+fn shift(op: &BinOp, value: u128, mask: u128, k: u32, signed: bool) -> (u128, u128) {
+    match op {
+        BinOp::Shl => (value << k, mask << k),
+        // Unknown sign bits stay unknown
+        _ if signed => (
+            ((value as i128) >> k) as u128,
+            ((mask as i128) >> k) as u128,
+        ),
+        _ => (value >> k, mask >> k),
+    }
+}
+
+/// Brings bits computed modulo 2^128 to the width of `ty`, like a wrapping operation would. This
+/// is sound whatever the overflow semantics, since a checked operation that overflows panics.
+fn fit(value: u128, mask: u128, ty: Option<IntType>) -> KnownBits {
+    let ty = match ty {
+        Some(ty) if ty.bits() < 128 => ty,
+        _ => return KnownBits::new(value, mask),
+    };
+    let low = (1u128 << ty.bits()) - 1;
+    let sign = 1u128 << (ty.bits() - 1);
+    let (value, mask) = (value & low, mask & low);
+    if !ty.signed {
+        KnownBits::new(value, mask)
+    } else if mask & sign != 0 {
+        KnownBits::new(value, mask | !low)
+    } else if value & sign != 0 {
+        KnownBits::new(value | !low, mask)
+    } else {
+        KnownBits::new(value, mask)
+    }
+}
+
+fn full(ty: Option<IntType>) -> KnownBits {
+    fit(0, !0, ty)
+}
+
+fn binop(op: &BinOp, v1: u128, m1: u128, v2: u128, m2: u128, arith: &Arith) -> KnownBits {
+    let (value, mask) = match op {
+        BinOp::BitAnd => {
+            let value = v1 & v2;
+            (value, (v1 | m1) & (v2 | m2) & !value)
+        }
+        BinOp::BitOr => {
+            let value = v1 | v2;
+            (value, (m1 | m2) & !value)
+        }
+        BinOp::BitXor => (v1 ^ v2, m1 | m2),
+        BinOp::Add => add(v1, m1, v2, m2),
+        BinOp::Sub => sub(v1, m1, v2, m2),
+        BinOp::Mul => mul(v1, m1, v2, m2),
+        BinOp::Div | BinOp::Rem => return divide(op, v1, m1, v2, m2, arith),
+        BinOp::Shl | BinOp::Shr => {
+            let ty = match arith.ty {
+                Some(ty) => ty,
+                None => return KnownBits::top(),
+            };
+            // Join the results of all the possible shift amounts. Larger amounts panic with
+            // overflow checks, and are masked without them.
+            let bits = ty.bits() as u32;
+            let significant = if arith.is_exact() {
+                !0
+            } else {
+                u128::from(bits - 1)
+            };
+            let mut result = Bottom;
+            for k in 0..bits {
+                if (u128::from(k) ^ v2) & !m2 & significant == 0 {
+                    let (value, mask) = shift(op, v1, m1, k, ty.signed);
+                    result = KnownBits::join(&result, &fit(value, mask, arith.ty));
+                }
+            }
+            return result;
+        }
+        // Comparisons give booleans, and offsets pointers
+        _ => return KnownBits::top(),
+    };
+    fit(value, mask, arith.ty)
+}
+
+fn divide(op: &BinOp, v1: u128, m1: u128, v2: u128, m2: u128, arith: &Arith) -> KnownBits {
+    let ty = match arith.ty {
+        Some(ty) => ty,
+        None => return KnownBits::top(),
+    };
+    if m2 == 0 && v2 == 0 {
+        // Division by zero panics
+        return Bottom;
+    }
+    if m1 == 0 && m2 == 0 {
+        let result = if ty.signed {
+            let (x, y) = (v1 as i128, v2 as i128);
+            match op {
+                BinOp::Div => x.checked_div(y),
+                _ => x.checked_rem(y),
+            }
+            .map(|r| r as u128)
+        } else {
+            match op {
+                BinOp::Div => v1.checked_div(v2),
+                _ => v1.checked_rem(v2),
+            }
+        };
+        // `MIN / -1` panics
+        return match result {
+            Some(r) if !ty.signed || ty.contains(r as i128) => fit(r, 0, arith.ty),
+            _ => Bottom,
+        };
+    }
+    // Unsigned division by 2^k is a shift, and the remainder a mask
+    if !ty.signed && m2 == 0 && v2.is_power_of_two() {
+        let k = v2.trailing_zeros();
+        return match op {
+            BinOp::Div => fit(v1 >> k, m1 >> k, arith.ty),
+            _ => fit(v1 & (v2 - 1), m1 & (v2 - 1), arith.ty),
+        };
+    }
+    full(arith.ty)
+}
+
+impl SimpleLattice for KnownBits {
+    fn applies(ty: &TyKind) -> bool {
+        match ty {
+            TyKind::Int(_) | TyKind::Uint(_) => true,
+            _ => false,
+        }
+    }
+
+    fn bot() -> Self {
+        Bottom
+    }
+
+    fn top() -> Self {
+        Bits { value: 0, mask: !0 }
+    }
+
+    fn join(op1: &Self, op2: &Self) -> Self {
+        match (*op1, *op2) {
+            (Bottom, a) | (a, Bottom) => a,
+            (
+                Bits {
+                    value: v1,
+                    mask: m1,
+                },
+                Bits {
+                    value: v2,
+                    mask: m2,
+                },
+            ) => KnownBits::new(v1, m1 | m2 | (v1 ^ v2)),
+        }
+    }
+
+    fn alpha(ty: &TyKind, size: Size, a: ConstValue) -> Self {
+        match ConstInt::decode(ty, size, a) {
+            Some(ConstInt::Signed(x)) => KnownBits::constant(x as u128),
+            Some(ConstInt::Unsigned(x)) => KnownBits::constant(x),
+            None => KnownBits::top(),
+        }
+    }
+
+    fn flow_binop(op: &BinOp, arg1: &Self, arg2: &Self, arith: &Arith) -> Self {
+        match (*arg1, *arg2) {
+            (
+                Bits {
+                    value: v1,
+                    mask: m1,
+                },
+                Bits {
+                    value: v2,
+                    mask: m2,
+                },
+            ) => binop(op, v1, m1, v2, m2, arith),
+            _ => Bottom,
+        }
+    }
+
+    fn flow_unop(op: &UnOp, arg: &Self, arith: &Arith) -> Self {
+        let (value, mask) = match *arg {
+            Bits { value, mask } => (value, mask),
+            Bottom => return Bottom,
+        };
+        match op {
+            UnOp::Not => fit(!value & !mask, mask, arith.ty),
+            UnOp::Neg => {
+                let (value, mask) = sub(0, 0, value, mask);
+                fit(value, mask, arith.ty)
+            }
+        }
+    }
+
+    fn flow_cond_true(op: &BinOp, arg1: &Self, arg2: &Self) -> (Self, Self) {
+        match op {
+            BinOp::Eq => {
+                let both = arg1.meet(arg2);
+                (both, both)
+            }
+            BinOp::Ne => match (arg1.as_constant(), arg2.as_constant()) {
+                (Some(x), Some(y)) if x == y => (Bottom, Bottom),
+                _ => (*arg1, *arg2),
+            },
+            _ => (*arg1, *arg2),
+        }
+    }
+
+    fn flow_cond_false(op: &BinOp, arg1: &Self, arg2: &Self) -> (Self, Self) {
+        match op {
+            BinOp::Eq => Self::flow_cond_true(&BinOp::Ne, arg1, arg2),
+            BinOp::Ne => Self::flow_cond_true(&BinOp::Eq, arg1, arg2),
+            _ => (*arg1, *arg2),
+        }
+    }
+
+    fn flow_cast(kind: &CastKind, from_ty: &TyKind, to_ty: &TyKind, arg: &Self) -> Self {
+        let (value, mask) = match (kind, *arg) {
+            (_, Bottom) => return Bottom,
+            (CastKind::Misc, Bits { value, mask }) if Self::applies(from_ty) => (value, mask),
+            _ => return KnownBits::top(),
+        };
+        // Casting truncates or extends to the target type, whose width may be any pointer width
+        [16, 32, 64].iter().fold(Bottom, |result, &bits| {
+            let to = IntType::of(to_ty, Size::from_bits(bits));
+            KnownBits::join(&result, &fit(value, mask, to))
+        })
+    }
+}
@@ -1,40 +1,432 @@
 use std::fmt::Debug;
 
+use crate::equalities::Equalities;
+use crate::int::{ConstInt, IntType};
 use rustc::mir::interpret::ConstValue;
-use rustc::mir::{BinOp, Operand, Place, PlaceBase, Rvalue, UnOp};
-use rustc::mir::{Local, LocalDecl};
-use rustc::ty::TyKind;
+use rustc::mir::visit::Visitor;
+use rustc::mir::{
+    AggregateKind, BinOp, BorrowKind, CastKind, Constant, Operand, Place, PlaceBase,
+    ProjectionElem, Rvalue, Statement, StatementKind, TerminatorKind, UnOp,
+};
+use rustc::mir::{BasicBlock, Local, LocalDecl, Location, Mir};
+use rustc::ty::layout::Size;
+use rustc::ty::{Ty, TyKind};
 use rustc_data_structures::indexed_vec::IndexVec;
 use std::collections::HashMap;
+use std::ops::Index;
+use std::sync::Arc;
 
 pub trait SimpleLattice: PartialEq + Eq + Copy + Debug {
     fn applies(ty: &TyKind) -> bool;
     fn bot() -> Self;
     fn top() -> Self;
     fn join(op1: &Self, op2: &Self) -> Self;
-    fn alpha(a: ConstValue) -> Self;
-    fn flow_binop(op: &BinOp, arg1: &Self, arg2: &Self) -> Self;
-    fn flow_unop(op: &UnOp, arg: &Self) -> Self;
+    /// Abstraction of a constant of type `ty`. `size` is the size of the constant, which is zero
+    /// if it is not an integer (see `ConstInt::decode`).
+    fn alpha(ty: &TyKind, size: Size, a: ConstValue) -> Self;
+    fn flow_binop(op: &BinOp, arg1: &Self, arg2: &Self, arith: &Arith) -> Self;
+    fn flow_unop(op: &UnOp, arg: &Self, arith: &Arith) -> Self;
     fn flow_cond_true(op: &BinOp, arg1: &Self, arg2: &Self) -> (Self, Self);
     fn flow_cond_false(op: &BinOp, arg1: &Self, arg2: &Self) -> (Self, Self);
+
+    // The following hooks are optional. Their defaults are sound (they know nothing about the result).
+
+    /// Value of `arg as to_ty`, where `arg` has type `from_ty`
+    fn flow_cast(_kind: &CastKind, _from_ty: &TyKind, _to_ty: &TyKind, _arg: &Self) -> Self {
+        Self::top()
+    }
+
+    /// Value of the length of the array or slice at `place`
+    fn flow_len(_place: &Place) -> Self {
+        Self::top()
+    }
+
+    /// Value of an aggregate (tuple, array, struct, ...) built from `args`
+    fn flow_aggregate(_kind: &AggregateKind, _args: &[Self]) -> Self {
+        Self::top()
+    }
+
+    /// Value of the discriminant of the enum at `place`
+    fn flow_discriminant(_place: &Place) -> Self {
+        Self::top()
+    }
+
+    /// Refinement of `dividend` knowing that `dividend % divisor == remainder`
+    fn flow_rem_cond(dividend: &Self, _divisor: &Self, _remainder: &Self) -> Self {
+        *dividend
+    }
+
+    /// Widening of `op1` (the previous value at a widening point) by `op2` (the new one). Domains
+    /// with infinite ascending chains must override this to ensure termination.
+    fn widen(op1: &Self, op2: &Self, _hints: &WideningHints) -> Self {
+        Self::join(op1, op2)
+    }
+}
+
+/// What the engine knows about a value being widened
+#[derive(Debug, Clone, Copy)]
+pub struct WideningHints<'a> {
+    /// Type of the value, if it is an integer
+    pub ty: Option<IntType>,
+    /// Integer constants of the function, sorted. Widening may stop at one of them instead of
+    /// going to the bounds of the type.
+    pub thresholds: &'a [i128],
+}
+
+/// What happens when arithmetic overflows in the analysed code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// Overflow silently wraps around (the code is built with `-O` and no overflow checks).
+    Wrapping,
+    /// The code is built with overflow checks. The result of a checked operation is exact on the
+    /// success edge of its overflow `Assert`, but not on the failure edge.
+    Checked,
+    /// Overflow aborts the program (overflow checks and `panic=abort`), so results are always exact.
+    Panicking,
+}
+
+/// Semantics of an arithmetic operation, as given to the transfer functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arith {
+    /// Type of the (first) operand, if it is an integer
+    pub ty: Option<IntType>,
+    /// Whether the operand is a `bool` (whose `ty` is `u8`)
+    pub boolean: bool,
+    pub overflow: Overflow,
+}
+
+impl Arith {
+    /// Whether the result of the operation is the mathematical one. If not, it may have wrapped around.
+    pub fn is_exact(&self) -> bool {
+        self.overflow != Overflow::Wrapping
+    }
+}
+
+/// Information about the function being analysed that transfer functions may need.
+pub struct Context<'a, 'tcx> {
+    pub decls: &'a IndexVec<Local, LocalDecl<'tcx>>,
+    /// Width of `isize` and `usize` on the target
+    pub pointer_size: Size,
+    pub overflow: Overflow,
+    /// Widening thresholds, see `thresholds`
+    pub thresholds: &'a [i128],
+    /// Locals that may be written through a pointer, see `escaping`
+    pub escaping: &'a IndexVec<Local, bool>,
 }
 
+impl<'a, 'tcx> Context<'a, 'tcx> {
+    pub fn int_type(&self, ty: Ty<'tcx>) -> Option<IntType> {
+        IntType::of(&ty.sty, self.pointer_size)
+    }
+
+    /// Abstraction of a constant operand
+    pub fn alpha<SL: SimpleLattice>(&self, constant: &Constant<'tcx>) -> SL {
+        let size = self.int_type(constant.ty).map_or(Size::ZERO, |ty| ty.size);
+        SL::alpha(&constant.ty.sty, size, constant.literal.val)
+    }
+
+    /// Semantics of an arithmetic operation whose first operand is `op`
+    pub fn arith(&self, op: &Operand<'tcx>) -> Arith {
+        let ty = self.operand_ty(op);
+        Arith {
+            ty: ty.and_then(|ty| self.int_type(ty)),
+            boolean: ty.map_or(false, |ty| ty.sty == TyKind::Bool),
+            overflow: self.overflow,
+        }
+    }
+
+    /// Type of an operand, if it can be found without the type context (i.e. it is a local or a constant)
+    pub fn operand_ty(&self, op: &Operand<'tcx>) -> Option<Ty<'tcx>> {
+        match op {
+            Operand::Copy(Place::Base(PlaceBase::Local(local)))
+            | Operand::Move(Place::Base(PlaceBase::Local(local))) => Some(self.decls[*local].ty),
+            Operand::Constant(constant) => Some(constant.ty),
+            _ => None,
+        }
+    }
+}
+
+/// Integer literals and array lengths of a function, sorted and without duplicates
+pub fn thresholds(mir: &Mir, pointer_size: Size) -> Vec<i128> {
+    let mut collector = Thresholds {
+        pointer_size,
+        values: Vec::new(),
+    };
+    collector.visit_mir(mir);
+    for decl in mir.local_decls.iter() {
+        for ty in decl.ty.walk() {
+            if let TyKind::Array(_, len) = ty.sty {
+                collector.push(&len.ty.sty, len.val);
+            }
+        }
+    }
+    let mut values = collector.values;
+    values.sort();
+    values.dedup();
+    values
+}
+
+struct Thresholds {
+    pointer_size: Size,
+    values: Vec<i128>,
+}
+
+impl Thresholds {
+    fn push(&mut self, ty: &TyKind, value: ConstValue) {
+        let size = match IntType::of(ty, self.pointer_size) {
+            Some(int_type) => int_type.size,
+            None => return,
+        };
+        if let Some(value) = ConstInt::decode(ty, size, value).and_then(ConstInt::to_i128) {
+            self.values.push(value);
+        }
+    }
+}
+
+impl<'tcx> Visitor<'tcx> for Thresholds {
+    fn visit_constant(&mut self, constant: &Constant<'tcx>, location: Location) {
+        self.push(&constant.ty.sty, constant.literal.val);
+        self.super_constant(constant, location);
+    }
+
+    fn visit_rvalue(&mut self, rvalue: &Rvalue<'tcx>, location: Location) {
+        if let Rvalue::Repeat(_, count) = rvalue {
+            self.values.push(i128::from(*count));
+        }
+        self.super_rvalue(rvalue, location);
+    }
+}
+
+/// Locals that are borrowed mutably somewhere in a function. They may be written through a
+/// pointer at any time.
+pub fn escaping(mir: &Mir) -> IndexVec<Local, bool> {
+    let mut collector = Escaping {
+        locals: IndexVec::from_elem_n(false, mir.local_decls.len()),
+    };
+    collector.visit_mir(mir);
+    collector.locals
+}
+
+struct Escaping {
+    locals: IndexVec<Local, bool>,
+}
+
+impl<'tcx> Visitor<'tcx> for Escaping {
+    fn visit_rvalue(&mut self, rvalue: &Rvalue<'tcx>, location: Location) {
+        if let Rvalue::Ref(_, BorrowKind::Mut { .. }, place)
+        | Rvalue::Ref(_, BorrowKind::Unique, place) = rvalue
+        {
+            if let Some(local) = base_local(place) {
+                self.locals[local] = true;
+            }
+        }
+        self.super_rvalue(rvalue, location);
+    }
+}
+
+/// Local at the base of a place (e.g. `_1` for `(*_1).0`)
+pub fn base_local(place: &Place) -> Option<Local> {
+    let mut place = place;
+    loop {
+        match place {
+            Place::Base(PlaceBase::Local(local)) => return Some(*local),
+            Place::Base(_) => return None,
+            Place::Projection(proj) => place = &proj.base,
+        }
+    }
+}
+
+/// The effects of the edge from a terminator to `target` that don't depend on the lattice: the
+/// destination of a call is only written on its return edge, and `Assert`s go to `apply_assert`.
+/// Overrides of `Lattice::apply_terminator` should fall back on it.
+pub fn apply_edge<'tcx, L: Lattice>(
+    state: &mut L,
+    kind: &TerminatorKind<'tcx>,
+    target: BasicBlock,
+    ctx: &Context<'_, 'tcx>,
+) {
+    match *kind {
+        TerminatorKind::Call {
+            ref func,
+            ref args,
+            destination: Some((ref place, dest)),
+            ..
+        } if dest == target => state.apply_function_call(func, args, place),
+        TerminatorKind::Assert {
+            ref cond,
+            expected,
+            target: success,
+            ..
+        } => {
+            let mut on_failure = state.clone_for_branch();
+            state.apply_assert(&mut on_failure, cond, expected, ctx);
+            if target != success {
+                *state = on_failure;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// State of an analysis at a program point. Its transfer functions run on worker threads if
+/// `Config::threads` is more than 1: they must not format a `Ty` or an `Rvalue` with `{:?}`, which
+/// needs the type context of the main thread.
 pub trait Lattice: PartialEq + Eq + Sized + Clone + Debug {
     fn bot(decls: &IndexVec<Local, LocalDecl>) -> Self;
     fn top(decls: &IndexVec<Local, LocalDecl>) -> Self;
+    /// Whether no execution reaches a point with this state, e.g. on the edge of a branch whose
+    /// condition can't hold. The engine doesn't propagate such states. It must be false for
+    /// states that are only bottom because no local has a value yet (e.g. before `StorageLive`).
+    fn is_unreachable(&self) -> bool {
+        false
+    }
     fn join(op1: &Self, op2: &Self) -> Self;
-    fn flow_assign(
+    /// Widening of `op1` (the previous state at a widening point) by `op2` (the new one)
+    fn widen<'tcx>(op1: &Self, op2: &Self, _ctx: &Context<'_, 'tcx>) -> Self {
+        Self::join(op1, op2)
+    }
+    /// Transfer function of an assignment to `local`. `equalities` are the locals known to be
+    /// equal before the statement, which the engine keeps up to date.
+    fn apply_assign<'tcx>(
+        &mut self,
+        local: Local,
+        rvalue: &Box<Rvalue<'tcx>>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    );
+    /// Transfer function of the assignment of a branch condition. `self` becomes the state where
+    /// the condition is false and `on_true` (a `clone_for_branch` of `self`) the state where it is
+    /// true.
+    fn apply_branch<'tcx>(
+        &mut self,
+        on_true: &mut Self,
+        rvalue: &Box<Rvalue<'tcx>>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    );
+    fn apply_function_call(&mut self, func: &Operand, args: &Vec<Operand>, destination: &Place);
+
+    /// Copy of the state for another edge. Lattices with shared or copy-on-write storage can make
+    /// this cheaper than a deep clone.
+    fn clone_for_branch(&self) -> Self {
+        self.clone()
+    }
+
+    /// Refines the state knowing that `dividend % divisor == remainder`, e.g. on the true edge of
+    /// `x % 4 == 1`
+    fn apply_remainder<'tcx>(
+        &mut self,
+        _dividend: &Operand<'tcx>,
+        _divisor: &Operand<'tcx>,
+        _remainder: &Operand<'tcx>,
+        _equalities: &Equalities,
+        _ctx: &Context<'_, 'tcx>,
+    ) {
+    }
+
+    /// Transfer function for any statement. By default, assignments to locals go to `apply_assign`
+    /// and every other statement leaves the state unchanged.
+    fn apply_statement<'tcx>(
+        &mut self,
+        stmt: &Statement<'tcx>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        if let StatementKind::Assign(Place::Base(PlaceBase::Local(local)), ref rvalue) = stmt.kind {
+            self.apply_assign(local, rvalue, equalities, ctx);
+        }
+    }
+
+    /// Refines `self` into the state of the success edge (where `cond == expected`) and
+    /// `on_failure` into the state of the failure edge of an `Assert`
+    fn apply_assert<'tcx>(
+        &mut self,
+        _on_failure: &mut Self,
+        _cond: &Operand<'tcx>,
+        _expected: bool,
+        _ctx: &Context<'_, 'tcx>,
+    ) {
+    }
+
+    /// Turns `self` into the state on the edge from a terminator to `target`. By default, this is
+    /// `apply_edge`, and every other terminator (`Drop`, `DropAndReplace`, `Yield`, `Abort`, ...)
+    /// leaves the state unchanged.
+    fn apply_terminator<'tcx>(
+        &mut self,
+        kind: &TerminatorKind<'tcx>,
+        target: BasicBlock,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        apply_edge(self, kind, target, ctx)
+    }
+
+    // Value-returning versions of the transfer functions, kept for compatibility
+
+    fn flow_assign<'tcx>(
         &self,
         local: Local,
-        rvalue: &Box<Rvalue>,
-        equiv: &mut HashMap<Local, Vec<Local>>,
-    ) -> Self;
-    fn flow_branch(
+        rvalue: &Box<Rvalue<'tcx>>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) -> Self {
+        let mut r = self.clone();
+        r.apply_assign(local, rvalue, equalities, ctx);
+        r
+    }
+
+    /// States where the condition is false and true
+    fn flow_branch<'tcx>(
+        &self,
+        rvalue: &Box<Rvalue<'tcx>>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) -> (Self, Self) {
+        let mut on_false = self.clone();
+        let mut on_true = self.clone_for_branch();
+        on_false.apply_branch(&mut on_true, rvalue, equalities, ctx);
+        (on_false, on_true)
+    }
+
+    fn flow_function_call(&self, func: &Operand, args: &Vec<Operand>, destination: &Place) -> Self {
+        let mut r = self.clone();
+        r.apply_function_call(func, args, destination);
+        r
+    }
+
+    fn flow_statement<'tcx>(
         &self,
-        rvalue: &Box<Rvalue>,
-        equiv: &mut HashMap<Local, Vec<Local>>,
-    ) -> (Self, Self);
-    fn flow_function_call(&self, func: &Operand, args: &Vec<Operand>, destination: &Place) -> Self;
+        stmt: &Statement<'tcx>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) -> Self {
+        let mut r = self.clone();
+        r.apply_statement(stmt, equalities, ctx);
+        r
+    }
+
+    /// States for the success edge and the failure edge of an `Assert`
+    fn flow_assert<'tcx>(
+        &self,
+        cond: &Operand<'tcx>,
+        expected: bool,
+        ctx: &Context<'_, 'tcx>,
+    ) -> (Self, Self) {
+        let mut success = self.clone();
+        let mut failure = self.clone_for_branch();
+        success.apply_assert(&mut failure, cond, expected, ctx);
+        (success, failure)
+    }
+
+    fn flow_terminator<'tcx>(
+        &self,
+        kind: &TerminatorKind<'tcx>,
+        target: BasicBlock,
+        ctx: &Context<'_, 'tcx>,
+    ) -> Self {
+        let mut r = self.clone();
+        r.apply_terminator(kind, target, ctx);
+        r
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -88,23 +480,31 @@ impl SimpleLattice for PreciseSignAnalysis {
         }
     }
 
-    fn alpha(a: ConstValue) -> Self {
-        match a {
-            ConstValue::Scalar(scalar) => {
-                let x = scalar.to_i32().unwrap();
-                if x < 0 {
-                    Lower
-                } else if x > 0 {
-                    Greater
-                } else {
-                    Zero
-                }
-            }
-            _ => Top,
+    fn alpha(ty: &TyKind, size: Size, a: ConstValue) -> Self {
+        match ConstInt::decode(ty, size, a) {
+            Some(ConstInt::Signed(x)) if x < 0 => Lower,
+            Some(ConstInt::Signed(x)) if x > 0 => Greater,
+            Some(ConstInt::Unsigned(x)) if x > 0 => Greater,
+            Some(_) => Zero,
+            None => Top,
         }
     }
 
-    fn flow_binop(op: &BinOp, arg1: &Self, arg2: &Self) -> Self {
+    fn flow_binop(op: &BinOp, arg1: &Self, arg2: &Self, arith: &Arith) -> Self {
+        let may_wrap = match op {
+            BinOp::Add | BinOp::Sub | BinOp::Mul => !arith.is_exact(),
+            _ => false,
+        };
+        if may_wrap {
+            // Only the operations that can't overflow keep their sign
+            return match (op, arg1, arg2) {
+                (BinOp::Add, a, Zero) | (BinOp::Add, Zero, a) | (BinOp::Sub, a, Zero) => *a,
+                (BinOp::Sub, Zero, Greater) => Lower,
+                (BinOp::Sub, Zero, GreaterEqual) => LowerEqual,
+                (BinOp::Mul, Zero, _) | (BinOp::Mul, _, Zero) => Zero,
+                _ => Top,
+            };
+        }
         match op {
             BinOp::Add => match (arg1, arg2) {
                 (Greater, Zero)
@@ -160,20 +560,25 @@ impl SimpleLattice for PreciseSignAnalysis {
                 (Zero, _) => Zero,
                 _ => Top,
             },
-            BinOp::Rem => *arg1,
+            // The remainder has the sign of the dividend, or is zero
+            BinOp::Rem => match arg1 {
+                Greater => GreaterEqual,
+                Lower => LowerEqual,
+                _ => *arg1,
+            },
             _ => Top,
         }
     }
 
-    fn flow_unop(op: &UnOp, arg: &Self) -> Self {
+    fn flow_unop(op: &UnOp, arg: &Self, arith: &Arith) -> Self {
         match op {
             UnOp::Neg => match arg {
                 Zero => Zero,
                 Greater => Lower,
-                Lower => Greater,
+                Lower if arith.is_exact() => Greater,
                 GreaterEqual => LowerEqual,
-                LowerEqual => GreaterEqual,
-                _ => Top,
+                LowerEqual if arith.is_exact() => GreaterEqual,
+                _ => Top, // The negation of the minimum value wraps around to itself
             },
             _ => Top,
         }
@@ -267,198 +672,465 @@ impl SimpleLattice for PreciseSignAnalysis {
             _ => (*arg1, *arg2),
         }
     }
+
+    fn flow_cast(kind: &CastKind, from_ty: &TyKind, to_ty: &TyKind, arg: &Self) -> Self {
+        match (kind, from_ty, to_ty) {
+            // Widening casts between signed integers keep the sign
+            (CastKind::Misc, TyKind::Int(from), TyKind::Int(to)) => {
+                match (from.bit_width(), to.bit_width()) {
+                    _ if from == to => *arg,
+                    (Some(from), Some(to)) if from <= to => *arg,
+                    _ => Top,
+                }
+            }
+            // Unsigned integers fit in a strictly wider signed integer
+            (CastKind::Misc, TyKind::Uint(from), TyKind::Int(to)) => {
+                match (from.bit_width(), to.bit_width()) {
+                    (Some(from), Some(to)) if from < to => GreaterEqual,
+                    _ => Top,
+                }
+            }
+            _ => Top,
+        }
+    }
+
+    fn flow_len(_place: &Place) -> Self {
+        GreaterEqual
+    }
+}
+
+/// Storage of a map lattice, which gives a `SimpleLattice` value to each tracked local. Locals
+/// whose type the `SimpleLattice` doesn't apply to are not tracked.
+pub trait LocalStore: PartialEq + Eq + Clone + Debug {
+    type Value: SimpleLattice;
+
+    /// A map without tracked locals, for a function with `num_locals` locals
+    fn empty(num_locals: usize) -> Self;
+    fn get(&self, local: &Local) -> Option<&Self::Value>;
+    fn get_mut(&mut self, local: &Local) -> Option<&mut Self::Value>;
+    fn insert(&mut self, local: Local, value: Self::Value);
+    fn tracked(&self) -> Vec<Local>;
+
+    fn contains_key(&self, local: &Local) -> bool {
+        self.get(local).is_some()
+    }
+
+    /// Whether both maps are known to be equal without comparing them
+    fn shares_storage(&self, _other: &Self) -> bool {
+        false
+    }
+}
+
+impl<SL: SimpleLattice> LocalStore for HashMap<Local, SL> {
+    type Value = SL;
+
+    fn empty(_num_locals: usize) -> Self {
+        HashMap::new()
+    }
+
+    fn get(&self, local: &Local) -> Option<&SL> {
+        HashMap::get(self, local)
+    }
+
+    fn get_mut(&mut self, local: &Local) -> Option<&mut SL> {
+        HashMap::get_mut(self, local)
+    }
+
+    fn insert(&mut self, local: Local, value: SL) {
+        HashMap::insert(self, local, value);
+    }
+
+    fn tracked(&self) -> Vec<Local> {
+        self.keys().cloned().collect()
+    }
+}
+
+/// A map lattice stored densely, indexed by local. `None` marks locals that are not tracked. The
+/// storage is shared between clones until one of them is modified, so cloning a state is cheap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalMap<SL> {
+    values: Arc<IndexVec<Local, Option<SL>>>,
+}
+
+impl<SL: SimpleLattice> LocalMap<SL> {
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (Local, &'a SL)> + 'a {
+        self.values
+            .iter_enumerated()
+            .filter_map(|(local, value)| value.as_ref().map(|value| (local, value)))
+    }
+}
+
+impl<SL: SimpleLattice> LocalStore for LocalMap<SL> {
+    type Value = SL;
+
+    fn empty(num_locals: usize) -> Self {
+        LocalMap {
+            values: Arc::new(IndexVec::from_elem_n(None, num_locals)),
+        }
+    }
+
+    fn get(&self, local: &Local) -> Option<&SL> {
+        self.values.get(*local).and_then(Option::as_ref)
+    }
+
+    fn get_mut(&mut self, local: &Local) -> Option<&mut SL> {
+        // Don't copy the storage for locals that aren't tracked
+        if !self.contains_key(local) {
+            return None;
+        }
+        Arc::make_mut(&mut self.values)[*local].as_mut()
+    }
+
+    fn insert(&mut self, local: Local, value: SL) {
+        Arc::make_mut(&mut self.values)[local] = Some(value);
+    }
+
+    fn tracked(&self) -> Vec<Local> {
+        self.iter().map(|(local, _)| local).collect()
+    }
+
+    fn shares_storage(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.values, &other.values)
+    }
+}
+
+impl<'a, SL: SimpleLattice> Index<&'a Local> for LocalMap<SL> {
+    type Output = SL;
+
+    fn index(&self, local: &Local) -> &SL {
+        self.get(local).expect("local is not tracked")
+    }
 }
 
-impl<SL: SimpleLattice> Lattice for HashMap<Local, SL> {
+impl<M: LocalStore> Lattice for M {
     fn bot(decls: &IndexVec<Local, LocalDecl>) -> Self {
-        let mut r = HashMap::new();
+        let mut r = M::empty(decls.len());
         for (local, decl) in decls.iter_enumerated() {
-            if SL::applies(&decl.ty.sty) {
-                r.insert(local, SL::bot());
+            if M::Value::applies(&decl.ty.sty) || is_checked_result::<M::Value>(&decl.ty.sty) {
+                r.insert(local, M::Value::bot());
             }
         }
         r
     }
 
     fn top(decls: &IndexVec<Local, LocalDecl>) -> Self {
-        let mut r = HashMap::new();
+        let mut r = M::empty(decls.len());
         for (local, decl) in decls.iter_enumerated() {
-            if SL::applies(&decl.ty.sty) {
-                r.insert(local, SL::top());
+            if M::Value::applies(&decl.ty.sty) || is_checked_result::<M::Value>(&decl.ty.sty) {
+                r.insert(local, M::Value::top());
             }
         }
         r
     }
 
     fn join(op1: &Self, op2: &Self) -> Self {
-        let mut newlattice = HashMap::new();
-        for key in op1.keys() {
-            newlattice.insert(*key, SL::join(&op1[key], &op2[key]));
+        if op1.shares_storage(op2) {
+            return op1.clone();
+        }
+        // A local tracked in only one of the maps keeps its value
+        let mut newlattice = op1.clone();
+        for key in op2.tracked() {
+            let value2 = *op2.get(&key).unwrap();
+            let joined = match op1.get(&key) {
+                Some(value1) => M::Value::join(value1, &value2),
+                None => value2,
+            };
+            if op1.get(&key) != Some(&joined) {
+                newlattice.insert(key, joined);
+            }
         }
         newlattice
     }
 
-    fn flow_assign(
-        &self,
+    fn widen<'tcx>(op1: &Self, op2: &Self, ctx: &Context<'_, 'tcx>) -> Self {
+        if op1.shares_storage(op2) {
+            return op1.clone();
+        }
+        let mut newlattice = op1.clone();
+        for key in op2.tracked() {
+            let value2 = *op2.get(&key).unwrap();
+            let widened = match op1.get(&key) {
+                Some(value1) => {
+                    let hints = WideningHints {
+                        ty: ctx.int_type(ctx.decls[key].ty),
+                        thresholds: ctx.thresholds,
+                    };
+                    M::Value::widen(value1, &value2, &hints)
+                }
+                None => value2,
+            };
+            if op1.get(&key) != Some(&widened) {
+                newlattice.insert(key, widened);
+            }
+        }
+        newlattice
+    }
+
+    fn apply_assign<'tcx>(
+        &mut self,
         local: Local,
-        rvalue: &Box<Rvalue>,
-        equiv: &mut HashMap<Local, Vec<Local>>,
-    ) -> Self {
+        rvalue: &Box<Rvalue<'tcx>>,
+        _equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
         if !self.contains_key(&local) {
-            return self.clone();
-        }
-        let get_val = |op: &Operand| match op {
-            Operand::Copy(place) | Operand::Move(place) => match place {
-                Place::Base(place_base) => match place_base {
-                    PlaceBase::Local(local2) => self[local2].clone(),
-                    _ => SL::top(),
-                },
-                _ => SL::top(),
-            },
-            Operand::Constant(constant) => SL::alpha(constant.literal.val),
-        };
-        let get_local = |op: &Operand| match op {
-            Operand::Copy(place) | Operand::Move(place) => match place {
-                Place::Base(place_base) => match place_base {
-                    PlaceBase::Local(local) => Some(local.clone()),
-                    _ => None,
-                },
-                _ => None,
-            },
-            _ => None,
+            return;
+        }
+        let val = if ctx.escaping[local] {
+            // The value may be modified through a reference at any time, so we can't know it
+            M::Value::top()
+        } else {
+            assigned_value(self, rvalue, ctx)
         };
+        *(self.get_mut(&local).unwrap()) = val;
+    }
 
-        let mut newlattice = self.clone();
-        let val = match &**rvalue {
-            Rvalue::Use(op) => {
-                if let Some(local2) = get_local(op) {
-                    equiv
-                        .entry(local)
-                        .or_insert_with(|| Vec::new())
-                        .push(local2);
-                    equiv
-                        .entry(local2)
-                        .or_insert_with(|| Vec::new())
-                        .push(local);
-                }
-                get_val(op)
+    fn apply_branch<'tcx>(
+        &mut self,
+        on_true: &mut Self,
+        rvalue: &Box<Rvalue<'tcx>>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        if let Rvalue::BinaryOp(op, op1, op2) | Rvalue::CheckedBinaryOp(op, op1, op2) = &**rvalue {
+            // Locals that may be modified through a reference stay top
+            let tracked = |local: &Local| self.contains_key(local) && !ctx.escaping[*local];
+            let local1 = operand_local(op1).filter(tracked);
+            let local2 = operand_local(op2).filter(tracked);
+            if local1.is_none() && local2.is_none() {
+                return;
             }
-            Rvalue::BinaryOp(op, op1, op2) | Rvalue::CheckedBinaryOp(op, op1, op2) => {
-                let op1 = get_val(op1);
-                let op2 = get_val(op2);
-                SL::flow_binop(op, &op1, &op2)
-            }
-            Rvalue::UnaryOp(op, op1) => {
-                let op1 = get_val(op1);
-                SL::flow_unop(op, &op1)
+            let val1 = eval_operand(self, op1, ctx);
+            let val2 = eval_operand(self, op2, ctx);
+            let (false1, false2) = M::Value::flow_cond_false(op, &val1, &val2);
+            let (true1, true2) = M::Value::flow_cond_true(op, &val1, &val2);
+            for &(local, on_false_val, on_true_val) in
+                [(local1, false1, true1), (local2, false2, true2)].iter()
+            {
+                if let Some(local) = local {
+                    refine(self, equalities, local, on_false_val);
+                    refine(on_true, equalities, local, on_true_val);
+                }
             }
-            _ => SL::top(),
-        };
-        *(newlattice.get_mut(&local).unwrap()) = val;
-        return newlattice;
+        }
     }
 
-    fn flow_branch(
-        &self,
-        rvalue: &Box<Rvalue>,
-        equiv: &mut HashMap<Local, Vec<Local>>,
-    ) -> (Self, Self) {
-        let get_val = |op: &Operand| match op {
-            Operand::Copy(place) | Operand::Move(place) => match place {
-                Place::Base(place_base) => match place_base {
-                    PlaceBase::Local(local2) => self[local2].clone(),
-                    _ => SL::top(),
-                },
-                _ => SL::top(),
-            },
-            Operand::Constant(constant) => SL::alpha(constant.literal.val),
-        };
-        let get_local = |op: &Operand| match op {
-            Operand::Copy(place) | Operand::Move(place) => match place {
-                Place::Base(place_base) => match place_base {
-                    PlaceBase::Local(local) => Some(local.clone()),
-                    _ => None,
-                },
-                _ => None,
-            },
-            _ => None,
-        };
+    fn apply_remainder<'tcx>(
+        &mut self,
+        dividend: &Operand<'tcx>,
+        divisor: &Operand<'tcx>,
+        remainder: &Operand<'tcx>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        // Locals that may be modified through a reference stay top
+        let tracked = |local: &Local| self.contains_key(local) && !ctx.escaping[*local];
+        if let Some(local) = operand_local(dividend).filter(tracked) {
+            let val = M::Value::flow_rem_cond(
+                &eval_operand(self, dividend, ctx),
+                &eval_operand(self, divisor, ctx),
+                &eval_operand(self, remainder, ctx),
+            );
+            refine(self, equalities, local, val);
+        }
+    }
 
-        match &**rvalue {
-            Rvalue::BinaryOp(op, op1, op2) | Rvalue::CheckedBinaryOp(op, op1, op2) => {
-                let local1 = get_local(op1);
-                let local2 = get_local(op2);
+    fn apply_function_call(&mut self, _func: &Operand, _args: &Vec<Operand>, destination: &Place) {
+        if let Place::Base(PlaceBase::Local(local)) = destination {
+            if let Some(p) = self.get_mut(&local) {
+                *p = M::Value::top();
+            }
+        }
+    }
 
-                if local1.is_some() && self.contains_key(&local1.unwrap())
-                    || local2.is_some() && self.contains_key(&local2.unwrap())
-                {
-                    let op1 = get_val(op1);
-                    let op2 = get_val(op2);
-
-                    let (l1, l2) = SL::flow_cond_false(op, &op1, &op2);
-                    let mut lattice1 = self.clone();
-                    if let Some(local) = local1 {
-                        *(lattice1.get_mut(&local).unwrap()) = l1;
-                        if let Some(equivs) = equiv.get(&local) {
-                            for local3 in equivs.iter() {
-                                *(lattice1.get_mut(&local3).unwrap()) = l1;
-                            }
-                        }
-                    }
-                    if let Some(local) = local2 {
-                        *(lattice1.get_mut(&local).unwrap()) = l2;
-                        if let Some(equivs) = equiv.get(&local) {
-                            for local3 in equivs.iter() {
-                                *(lattice1.get_mut(&local3).unwrap()) = l2;
-                            }
-                        }
+    fn apply_statement<'tcx>(
+        &mut self,
+        stmt: &Statement<'tcx>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        match stmt.kind {
+            StatementKind::Assign(Place::Base(PlaceBase::Local(local)), ref rvalue) => {
+                self.apply_assign(local, rvalue, equalities, ctx)
+            }
+            // Writing to part of a local (e.g. a field) changes its value
+            StatementKind::Assign(ref place, _)
+            | StatementKind::SetDiscriminant { ref place, .. } => {
+                if let Some(p) = base_local(place).and_then(|local| self.get_mut(&local)) {
+                    *p = M::Value::top();
+                }
+            }
+            StatementKind::InlineAsm(ref asm) => {
+                for place in asm.outputs.iter() {
+                    if let Some(p) = base_local(place).and_then(|local| self.get_mut(&local)) {
+                        *p = M::Value::top();
                     }
+                }
+            }
+            // The value of a local without storage can't be observed. Locals that may be modified
+            // through a reference stay top.
+            StatementKind::StorageLive(local) | StatementKind::StorageDead(local)
+                if !ctx.escaping[local] =>
+            {
+                if let Some(p) = self.get_mut(&local) {
+                    *p = M::Value::bot();
+                }
+            }
+            _ => {}
+        }
+    }
 
-                    let (l1, l2) = SL::flow_cond_true(op, &op1, &op2);
-                    let mut lattice2 = self.clone();
-                    if let Some(local) = local1 {
-                        *(lattice2.get_mut(&local).unwrap()) = l1;
-                        if let Some(equivs) = equiv.get(&local) {
-                            for local3 in equivs.iter() {
-                                *(lattice2.get_mut(&local3).unwrap()) = l1;
-                            }
-                        }
-                    }
-                    if let Some(local) = local2 {
-                        *(lattice2.get_mut(&local).unwrap()) = l2;
-                        if let Some(equivs) = equiv.get(&local) {
-                            for local3 in equivs.iter() {
-                                *(lattice2.get_mut(&local3).unwrap()) = l2;
-                            }
+    fn apply_assert<'tcx>(
+        &mut self,
+        on_failure: &mut Self,
+        cond: &Operand<'tcx>,
+        _expected: bool,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        if ctx.overflow == Overflow::Checked {
+            // An overflow check looks at field 1 of the result of a checked operation. If it
+            // fails, the result has wrapped around.
+            if let Operand::Copy(Place::Projection(proj)) | Operand::Move(Place::Projection(proj)) =
+                cond
+            {
+                if let (Place::Base(PlaceBase::Local(local)), ProjectionElem::Field(field, _)) =
+                    (&proj.base, &proj.elem)
+                {
+                    if field.index() == 1 {
+                        if let Some(p) = on_failure.get_mut(local) {
+                            *p = M::Value::top();
                         }
                     }
-                    (lattice1, lattice2)
-                } else {
-                    (self.clone(), self.clone())
                 }
             }
-            _ => (self.clone(), self.clone()),
         }
     }
 
-    fn flow_function_call(
-        &self,
-        _func: &Operand,
-        _args: &Vec<Operand>,
-        destination: &Place,
-    ) -> Self {
-        let mut r = self.clone();
-        match destination {
-            Place::Base(place_base) => match place_base {
-                PlaceBase::Local(local) => {
-                    if let Some(p) = r.get_mut(&local) {
-                        *p = SL::top();
+    fn apply_terminator<'tcx>(
+        &mut self,
+        kind: &TerminatorKind<'tcx>,
+        target: BasicBlock,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        match *kind {
+            // The new value is written even if dropping the old one panics
+            TerminatorKind::DropAndReplace {
+                ref location,
+                ref value,
+                ..
+            } => match location {
+                Place::Base(PlaceBase::Local(local))
+                    if self.contains_key(local) && !ctx.escaping[*local] =>
+                {
+                    let val = eval_operand(self, value, ctx);
+                    *(self.get_mut(local).unwrap()) = val;
+                }
+                _ => {
+                    if let Some(p) = base_local(location).and_then(|local| self.get_mut(&local)) {
+                        *p = M::Value::top();
                     }
                 }
-                _ => {}
             },
-            _ => {}
+            // A dropped local can't be used anymore
+            TerminatorKind::Drop {
+                location: Place::Base(PlaceBase::Local(local)),
+                ..
+            } if !ctx.escaping[local] => {
+                if let Some(p) = self.get_mut(&local) {
+                    *p = M::Value::bot();
+                }
+            }
+            _ => apply_edge(self, kind, target, ctx),
         }
-        r
+    }
+}
+
+/// Value of `rvalue` in a map lattice
+fn assigned_value<'tcx, M: LocalStore>(
+    map: &M,
+    rvalue: &Rvalue<'tcx>,
+    ctx: &Context<'_, 'tcx>,
+) -> M::Value {
+    let get_val = |op: &Operand<'tcx>| eval_operand(map, op, ctx);
+    match rvalue {
+        Rvalue::Use(op) => get_val(op),
+        Rvalue::BinaryOp(op, op1, op2) | Rvalue::CheckedBinaryOp(op, op1, op2) => {
+            let arith = ctx.arith(op1);
+            let op1 = get_val(op1);
+            let op2 = get_val(op2);
+            M::Value::flow_binop(op, &op1, &op2, &arith)
+        }
+        Rvalue::UnaryOp(op, op1) => {
+            let arith = ctx.arith(op1);
+            let op1 = get_val(op1);
+            M::Value::flow_unop(op, &op1, &arith)
+        }
+        Rvalue::Cast(kind, op1, ty) => match ctx.operand_ty(op1) {
+            Some(from_ty) => M::Value::flow_cast(kind, &from_ty.sty, &ty.sty, &get_val(op1)),
+            None => M::Value::top(),
+        },
+        Rvalue::Len(place) => M::Value::flow_len(place),
+        Rvalue::Aggregate(kind, ops) => {
+            let args = ops.iter().map(|op| get_val(op)).collect::<Vec<_>>();
+            M::Value::flow_aggregate(kind, &args)
+        }
+        Rvalue::Discriminant(place) => M::Value::flow_discriminant(place),
+        _ => M::Value::top(),
+    }
+}
+
+/// Sets `local` and the locals known to be equal to it to `val`
+fn refine<M: LocalStore>(map: &mut M, equalities: &Equalities, local: Local, val: M::Value) {
+    for local in equalities.class(local) {
+        if let Some(p) = map.get_mut(&local) {
+            *p = val;
+        }
+    }
+}
+
+/// The local an operand reads, if it is a whole local
+pub(crate) fn operand_local(op: &Operand) -> Option<Local> {
+    match op {
+        Operand::Copy(Place::Base(PlaceBase::Local(local)))
+        | Operand::Move(Place::Base(PlaceBase::Local(local))) => Some(*local),
+        _ => None,
+    }
+}
+
+/// Whether `ty` is the type `(T, bool)` of the result of a checked operation on a `T` tracked by `SL`.
+/// Such locals are tracked as the value of their field 0.
+fn is_checked_result<SL: SimpleLattice>(ty: &TyKind) -> bool {
+    match ty {
+        TyKind::Tuple(tys) if tys.len() == 2 => match tys[1].sty {
+            TyKind::Bool => SL::applies(&tys[0].sty),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Value of an operand in a map lattice
+fn eval_operand<'tcx, M: LocalStore>(
+    map: &M,
+    op: &Operand<'tcx>,
+    ctx: &Context<'_, 'tcx>,
+) -> M::Value {
+    match op {
+        Operand::Copy(place) | Operand::Move(place) => match place {
+            Place::Base(PlaceBase::Local(local)) => {
+                map.get(local).cloned().unwrap_or_else(M::Value::top)
+            }
+            // Field 0 of the result of a checked operation
+            Place::Projection(proj) => match (&proj.base, &proj.elem) {
+                (Place::Base(PlaceBase::Local(local)), ProjectionElem::Field(field, _))
+                    if field.index() == 0 =>
+                {
+                    map.get(local).cloned().unwrap_or_else(M::Value::top)
+                }
+                _ => M::Value::top(),
+            },
+            _ => M::Value::top(),
+        },
+        Operand::Constant(constant) => ctx.alpha::<M::Value>(constant),
     }
 }
@@ -10,250 +10,538 @@ pub use rustc::ty;
 pub use rustc_data_structures::indexed_vec::IndexVec;
 
 mod block;
+pub mod cfg;
+pub mod config;
+pub mod congruence;
+pub mod disjunctive;
+pub mod dominators;
+pub mod equalities;
+pub mod flat;
+pub mod gen_kill;
+pub mod int;
+pub mod interval;
+pub mod known_bits;
 pub mod lattice;
+pub mod linear_equalities;
+pub mod loops;
+pub mod octagon;
+mod parallel;
+pub mod product;
+mod relational;
+pub mod worklist;
+pub mod wto;
 
-use block::Block;
+pub use config::Config;
+
+use config::{Exceeded, OnExceeded};
+
+use cfg::ControlFlow;
+use equalities::Equalities;
+use gen_kill::{BitSet, GenKill, GenKillAnalysis};
+use parallel::SharedMir;
+
+use lattice::{operand_local, Context, Overflow};
 use rustc::hir::def_id::LOCAL_CRATE;
 use rustc::mir::{
-    BasicBlock, BasicBlockData, Mir, Operand, Place, PlaceBase, Rvalue, Statement, StatementKind,
-    TerminatorKind, UnOp, START_BLOCK,
+    BasicBlock, BinOp, Local, Location, Mir, Operand, Place, PlaceBase, Rvalue, Statement,
+    StatementKind, TerminatorKind, UnOp, START_BLOCK,
 };
+use rustc::ty::layout::Size;
 use rustc::ty::TyKind;
 use rustc_interface::interface;
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::collections::{HashMap, HashSet};
 use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use worklist::{Metrics, Strategy, Worklist};
+use wto::{Component, Wto};
 
-fn reverse_post_order(
-    blocks: &IndexVec<BasicBlock, BasicBlockData>,
-    block: BasicBlock,
-    order: &mut HashMap<BasicBlock, usize>,
-    visited: &mut HashSet<BasicBlock>,
-    idx: &mut usize,
-) {
-    if visited.contains(&block) {
-        return;
-    } else {
-        visited.insert(block);
-    }
-    let data = &blocks[block];
-    for &suc in data.terminator().successors() {
-        reverse_post_order(blocks, suc, order, visited, idx);
-    }
-    order.insert(block, *idx);
-    *idx += 1;
+/// Where a statement is, for checkers that need control flow information
+pub struct Site<'a> {
+    pub location: Location,
+    pub cfg: &'a ControlFlow,
 }
 
 struct Analysis<'tcx, L: lattice::Lattice> {
-    function_mir: &'tcx Mir<'tcx>,
-    input: IndexVec<BasicBlock, L>,
+    function_mir: SharedMir<'tcx>,
+    cfg: ControlFlow,
+    /// Input of each block that keeps its state. The input of any other block is only kept
+    /// between the visit of its predecessor and its own visit.
+    input: IndexVec<BasicBlock, Option<L>>,
+    /// Locals known to be equal at the start of each block, kept along with its input. `None`
+    /// until the block is reached.
+    equalities: IndexVec<BasicBlock, Option<Equalities>>,
+    /// Blocks whose state is kept: all of them, or only join points and loop heads when the
+    /// analysis is sparse
+    stored: IndexVec<BasicBlock, bool>,
+    /// Priority of each reachable block in the worklist
     order: HashMap<BasicBlock, usize>,
-    worklist: BinaryHeap<Block<'tcx>>,
+    worklist: Worklist,
+    /// Weak topological ordering, when it is used instead of the worklist
+    wto: Option<Wto>,
+    /// Blocks where the input is widened instead of joined
+    widening_points: HashSet<BasicBlock>,
+    /// Constants where widening may stop
+    thresholds: Vec<i128>,
+    /// Locals borrowed mutably somewhere in the function
+    escaping: Arc<IndexVec<Local, bool>>,
+    /// Blocks whose input is not bottom
+    reached: IndexVec<BasicBlock, bool>,
+    /// Blocks whose input changed since the last time their component was iterated
+    changed: IndexVec<BasicBlock, bool>,
+    pointer_size: Size,
+    config: Config,
+    started: Instant,
+    /// Set when the analysis goes over its budget
+    exceeded: Option<Exceeded>,
 }
 
 impl<'tcx, L: lattice::Lattice> Analysis<'tcx, L> {
-    fn new(function_mir: &'tcx Mir<'tcx>) -> Self {
-        let mut order = HashMap::new();
-        let mut idx = 0;
-        let mut visited = HashSet::new();
-        let mut input = IndexVec::new();
-        reverse_post_order(
-            function_mir.basic_blocks(),
-            START_BLOCK,
-            &mut order,
-            &mut visited,
-            &mut idx,
-        );
-
-        for _ in 0..function_mir.basic_blocks().len() {
-            input.push(L::bot(&function_mir.local_decls));
+    fn new(function_mir: &'tcx Mir<'tcx>, pointer_size: Size, config: &Config) -> Self {
+        let blocks = function_mir.basic_blocks();
+        let mut order = cfg::reverse_postorder_indices(blocks);
+        let cfg = ControlFlow::new(blocks);
+        let (wto, widening_points) = if let Strategy::WeakTopological = config.strategy {
+            let wto = Wto::new(blocks, &order);
+            let heads = wto.heads();
+            (Some(wto), heads)
+        } else {
+            // Loop headers, and targets of the retreating edges of irreducible loops
+            let mut widening_points = cfg.loops.headers();
+            for (&block, &i) in order.iter() {
+                for suc in blocks[block].terminator().successors() {
+                    if order[suc] <= i && !cfg.dominators.dominates(*suc, block) {
+                        widening_points.insert(*suc);
+                    }
+                }
+            }
+            (None, widening_points)
+        };
+        if let Strategy::Scc = config.strategy {
+            order = worklist::scc_priorities(blocks, &order);
+        }
+
+        let mut stored = IndexVec::from_elem_n(true, blocks.len());
+        if config.sparse {
+            // Any other block has a single predecessor, which is visited right before it
+            let predecessors = cfg::predecessors(blocks);
+            for (block, preds) in predecessors.iter_enumerated() {
+                stored[block] = preds.len() > 1 || widening_points.contains(&block);
+            }
         }
-        input[START_BLOCK] = L::top(&function_mir.local_decls);
-        let mut worklist = BinaryHeap::new();
-        let first_block = Block::new(
-            START_BLOCK,
-            &function_mir.basic_blocks()[START_BLOCK],
-            order[&START_BLOCK],
-        );
-        worklist.push(first_block);
+        stored[START_BLOCK] = true;
+
+        let bot = L::bot(&function_mir.local_decls);
+        let mut input = stored
+            .iter()
+            .map(|&stored| if stored { Some(bot.clone()) } else { None })
+            .collect::<IndexVec<BasicBlock, _>>();
+        input[START_BLOCK] = Some(L::top(&function_mir.local_decls));
+        let escaping = Arc::new(lattice::escaping(function_mir));
+        let mut equalities = IndexVec::from_elem_n(None, blocks.len());
+        equalities[START_BLOCK] = Some(Equalities::new(&escaping));
+        let mut reached = IndexVec::from_elem_n(false, blocks.len());
+        reached[START_BLOCK] = true;
+        let mut worklist = Worklist::new(config.strategy, blocks.len());
+        worklist.push(START_BLOCK, 0);
         Self {
-            function_mir,
+            function_mir: SharedMir(function_mir),
+            cfg,
             order,
             worklist,
+            wto,
+            widening_points,
+            thresholds: lattice::thresholds(function_mir, pointer_size),
+            escaping,
+            reached,
+            changed: IndexVec::from_elem_n(false, blocks.len()),
             input,
+            equalities,
+            stored,
+            pointer_size,
+            config: config.clone(),
+            started: Instant::now(),
+            exceeded: None,
+        }
+    }
+
+    fn context(&self) -> Context<'_, 'tcx> {
+        let mir = self.function_mir.0;
+        Context {
+            decls: &mir.local_decls,
+            pointer_size: self.pointer_size,
+            overflow: self.config.overflow,
+            thresholds: &self.thresholds,
+            escaping: &self.escaping,
         }
     }
 
     fn run(&mut self) {
+        self.started = Instant::now();
+        if let Some(wto) = self.wto.take() {
+            self.stabilise(&wto.components);
+            self.wto = Some(wto);
+        } else {
+            self.iterate();
+        }
+        if self.exceeded.is_some() && self.config.on_exceeded == OnExceeded::Top {
+            self.give_up();
+        }
+    }
+
+    fn iterate(&mut self) {
         while let Some(block) = self.worklist.pop() {
-            let mut lattice = self.input[block.id].clone();
-            let mut lattice2 = None;
-            let mut if_local_bool = None;
-            let mut reverse = false;
-            let mut equivs = HashMap::new();
-
-            // To be able to propagate conditional information
-            match block.data.terminator().kind {
-                TerminatorKind::SwitchInt {
-                    ref discr,
-                    ref switch_ty,
-                    values: _,
-                    targets: _,
-                } => {
-                    if let TyKind::Bool = switch_ty.sty {
-                        match discr {
-                            Operand::Copy(place) | Operand::Move(place) => match place {
-                                Place::Base(PlaceBase::Local(local)) => {
-                                    if_local_bool = Some(local);
-                                }
-                                _ => {}
-                            },
-                            _ => {}
-                        }
-                    }
+            if !self.within_budget() {
+                return;
+            }
+            let (input, equalities) = match self.take_input(block) {
+                Some(input) => input,
+                None => continue,
+            };
+            for (suc, output, equalities) in self.transfer(block, input, equalities) {
+                if self.propagate(suc, output, equalities) {
+                    // Unreachable blocks have no reverse postorder index
+                    let priority = self.order.get(&suc).cloned().unwrap_or(usize::max_value());
+                    self.worklist.push(suc, priority);
                 }
-                _ => {}
             }
-            // To propagate conditional information through a logical not (and thats as far as ill go)
-            if let Some(_) = if_local_bool {
-                for stmt in block.data.statements.iter() {
-                    if let StatementKind::Assign(
-                        Place::Base(PlaceBase::Local(ref local)),
-                        ref rvalue,
-                    ) = stmt.kind
-                    {
-                        if let Rvalue::UnaryOp(
-                            UnOp::Not,
-                            Operand::Copy(Place::Base(PlaceBase::Local(ref local2))),
-                        )
-                        | Rvalue::UnaryOp(
-                            UnOp::Not,
-                            Operand::Move(Place::Base(PlaceBase::Local(ref local2))),
-                        ) = **rvalue
-                        {
-                            if if_local_bool.unwrap() == local {
-                                if_local_bool = Some(local2);
-                                reverse = true;
-                            }
-                        }
+        }
+    }
+
+    /// Iterates the components of a weak topological ordering (Bourdoncle's recursive strategy)
+    fn stabilise(&mut self, components: &[Component]) {
+        for component in components {
+            match component {
+                Component::Vertex(block) => self.visit(*block),
+                Component::Cycle { head, body } => loop {
+                    self.changed[*head] = false;
+                    self.visit(*head);
+                    self.stabilise(body);
+                    if !self.changed[*head] || self.exceeded.is_some() {
+                        break;
                     }
-                }
+                },
             }
+        }
+    }
 
-            // Process statements in this block
-            for stmt in block.data.statements.iter() {
-                match stmt.kind {
-                    StatementKind::Assign(ref place, ref rvalue) => match place {
-                        Place::Base(place_base) => match place_base {
-                            PlaceBase::Local(local) => {
-                                if if_local_bool == Some(local) {
-                                    let r = lattice.flow_branch(rvalue, &mut equivs);
-                                    if reverse {
-                                        lattice = r.1;
-                                        lattice2 = Some(r.0);
-                                    } else {
-                                        lattice = r.0;
-                                        lattice2 = Some(r.1);
-                                    }
-                                } else {
-                                    lattice = lattice.flow_assign(*local, rvalue, &mut equivs);
-                                    if let Some(ref mut lattice2p) = lattice2 {
-                                        *lattice2p =
-                                            lattice2p.flow_assign(*local, rvalue, &mut equivs);
-                                    }
-                                }
+    fn visit(&mut self, block: BasicBlock) {
+        if self.exceeded.is_some() {
+            return;
+        }
+        let (input, equalities) = match self.take_input(block) {
+            Some(input) => input,
+            None => return,
+        };
+        self.worklist.metrics.iterations += 1;
+        if !self.within_budget() {
+            return;
+        }
+        for (suc, output, equalities) in self.transfer(block, input, equalities) {
+            if self.propagate(suc, output, equalities) {
+                self.changed[suc] = true;
+            }
+        }
+    }
+
+    /// Checks the iteration count and the elapsed time against the budget of the function
+    fn within_budget(&mut self) -> bool {
+        if let Some(max) = self.config.max_iterations {
+            if self.worklist.metrics.iterations > max {
+                self.exceeded = Some(Exceeded::Iterations(max));
+            }
+        }
+        if let Some(timeout) = self.config.timeout {
+            if self.started.elapsed() > timeout {
+                self.exceeded = Some(Exceeded::Timeout(timeout));
+            }
+        }
+        self.exceeded.is_none()
+    }
+
+    /// Replaces every state with top, which is sound whatever the fixpoint would have been
+    fn give_up(&mut self) {
+        let top = L::top(&self.function_mir.local_decls);
+        let none = Equalities::new(&self.escaping);
+        for (block, input) in self.input.iter_enumerated_mut() {
+            if self.stored[block] {
+                *input = Some(top.clone());
+                self.equalities[block] = Some(none.clone());
+            }
+            self.reached[block] = true;
+        }
+    }
+
+    /// Input of a block that is about to be visited and its equalities, if it was reached
+    fn take_input(&mut self, block: BasicBlock) -> Option<(L, Equalities)> {
+        if !self.reached[block] {
+            return None;
+        }
+        let (input, equalities) = if self.stored[block] {
+            (self.input[block].clone(), self.equalities[block].clone())
+        } else {
+            (self.input[block].take(), self.equalities[block].take())
+        };
+        let equalities = equalities.unwrap_or_else(|| Equalities::new(&self.escaping));
+        input.map(|input| (input, equalities))
+    }
+
+    /// Joins (or widens) `output` into the input of `suc`, and `equalities` into its
+    /// equalities. Returns whether either changed.
+    fn propagate(&mut self, suc: BasicBlock, output: L, equalities: Equalities) -> bool {
+        if !self.stored[suc] {
+            // The only predecessor of `suc` gives its whole input
+            if output.is_unreachable() {
+                return false;
+            }
+            self.input[suc] = Some(output);
+            self.equalities[suc] = Some(equalities);
+            self.reached[suc] = true;
+            return true;
+        }
+        // The equalities of an edge that can't be taken don't hold anything back. Partitions can
+        // only get finer, so they need no widening.
+        let newequalities = if output.is_unreachable() {
+            self.equalities[suc].clone()
+        } else {
+            Some(match self.equalities[suc] {
+                Some(ref old) => Equalities::join(old, &equalities),
+                None => equalities,
+            })
+        };
+        let old = self.input[suc].as_ref().unwrap();
+        let mut newinput = L::join(&output, old);
+        if self.widening_points.contains(&suc) {
+            newinput = L::widen(old, &newinput, &self.context());
+        }
+        if newinput != *old || newequalities != self.equalities[suc] {
+            self.input[suc] = Some(newinput);
+            self.equalities[suc] = newequalities;
+            self.reached[suc] = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Applies the transfer function of a block to its input. Returns the information on each
+    /// outgoing edge, with the equalities that hold on it.
+    fn transfer(
+        &self,
+        block: BasicBlock,
+        input: L,
+        mut equalities: Equalities,
+    ) -> Vec<(BasicBlock, L, Equalities)> {
+        let ctx = self.context();
+        let data = &self.function_mir.basic_blocks()[block];
+        let mut lattice = input;
+        let mut lattice2 = None;
+        let mut if_local_bool = None;
+        let mut reverse = false;
+
+        // To be able to propagate conditional information
+        match data.terminator().kind {
+            TerminatorKind::SwitchInt {
+                ref discr,
+                ref switch_ty,
+                values: _,
+                targets: _,
+            } => {
+                if let TyKind::Bool = switch_ty.sty {
+                    match discr {
+                        Operand::Copy(place) | Operand::Move(place) => match place {
+                            Place::Base(PlaceBase::Local(local)) => {
+                                if_local_bool = Some(local);
                             }
                             _ => {}
                         },
                         _ => {}
-                    },
-                    _ => {} // We only really care about assignments
+                    }
                 }
             }
-
-            // Process function call if it exists
-            match block.data.terminator().kind {
-                TerminatorKind::Call {
-                    ref func,
-                    ref args,
-                    ref destination,
-                    cleanup: _,
-                    from_hir_call: _,
-                } => {
-                    if let Some((place, _)) = destination {
-                        lattice = lattice.flow_function_call(func, args, place);
+            _ => {}
+        }
+        // To propagate conditional information through a logical not (and thats as far as ill go)
+        if let Some(_) = if_local_bool {
+            for stmt in data.statements.iter() {
+                if let StatementKind::Assign(Place::Base(PlaceBase::Local(ref local)), ref rvalue) =
+                    stmt.kind
+                {
+                    if let Rvalue::UnaryOp(
+                        UnOp::Not,
+                        Operand::Copy(Place::Base(PlaceBase::Local(ref local2))),
+                    )
+                    | Rvalue::UnaryOp(
+                        UnOp::Not,
+                        Operand::Move(Place::Base(PlaceBase::Local(ref local2))),
+                    ) = **rvalue
+                    {
+                        if if_local_bool.unwrap() == local {
+                            if_local_bool = Some(local2);
+                            reverse = true;
+                        }
                     }
                 }
-                _ => {}
             }
+        }
 
-            // Propagate information to block successors and add to worklist
-            let successors = block
-                .data
-                .terminator()
-                .successors()
-                .cloned()
-                .collect::<Vec<BasicBlock>>();
-            if if_local_bool.is_some() && lattice2.is_some() {
-                let newinput = L::join(&lattice, &self.input[successors[0]]);
-                if newinput != self.input[successors[0]] {
-                    self.input[successors[0]] = newinput;
-                    let b1 = Block::new(
-                        successors[0],
-                        &self.function_mir.basic_blocks()[successors[0]],
-                        self.order[&successors[0]],
-                    );
-                    self.worklist.push(b1);
-                }
-
-                let newinput = L::join(&lattice2.unwrap(), &self.input[successors[1]]);
-                if newinput != self.input[successors[1]] {
-                    self.input[successors[1]] = newinput;
-                    let b2 = Block::new(
-                        successors[1],
-                        &self.function_mir.basic_blocks()[successors[1]],
-                        self.order[&successors[1]],
-                    );
-                    self.worklist.push(b2);
+        // Process statements in this block
+        for (statement_index, stmt) in data.statements.iter().enumerate() {
+            match stmt.kind {
+                StatementKind::Assign(Place::Base(PlaceBase::Local(ref local)), ref rvalue)
+                    if if_local_bool == Some(local) =>
+                {
+                    let mut on_true = lattice.clone_for_branch();
+                    lattice.apply_branch(&mut on_true, rvalue, &equalities, &ctx);
+                    // `x % m == r` also tells something about `x`
+                    if let Rvalue::BinaryOp(op, op1, op2) = &**rvalue {
+                        let remainder = |op: &Operand| {
+                            let local = operand_local(op)?;
+                            self.remainder_operands(equalities.remainder(local)?)
+                        };
+                        let equation = match (remainder(op1), remainder(op2)) {
+                            (Some((dividend, divisor)), _) => Some((dividend, divisor, op2)),
+                            (_, Some((dividend, divisor))) => Some((dividend, divisor, op1)),
+                            _ => None,
+                        };
+                        let on_equal = match op {
+                            BinOp::Eq => Some(&mut on_true),
+                            BinOp::Ne => Some(&mut lattice),
+                            _ => None,
+                        };
+                        if let (Some((dividend, divisor, value)), Some(on_equal)) =
+                            (equation, on_equal)
+                        {
+                            on_equal.apply_remainder(&dividend, &divisor, value, &equalities, &ctx);
+                        }
+                    }
+                    if reverse {
+                        std::mem::swap(&mut lattice, &mut on_true);
+                    }
+                    lattice2 = Some(on_true);
                 }
-            } else {
-                for suc in successors.into_iter() {
-                    let newinput = L::join(&lattice, &self.input[suc]);
-                    if newinput != self.input[suc] {
-                        self.input[suc] = newinput;
-                        let b = Block::new(
-                            suc,
-                            &self.function_mir.basic_blocks()[suc],
-                            self.order[&suc],
-                        );
-                        self.worklist.push(b);
+                _ => {
+                    lattice.apply_statement(stmt, &equalities, &ctx);
+                    if let Some(ref mut lattice2p) = lattice2 {
+                        lattice2p.apply_statement(stmt, &equalities, &ctx);
                     }
                 }
             }
+            equalities.apply_statement(
+                stmt,
+                Location {
+                    block,
+                    statement_index,
+                },
+            );
+        }
+
+        // Compute the information on each outgoing edge
+        let terminator = data.terminator();
+        let successors = terminator
+            .successors()
+            .cloned()
+            .collect::<Vec<BasicBlock>>();
+        let mut outputs = Vec::with_capacity(successors.len());
+        if if_local_bool.is_some() && lattice2.is_some() {
+            outputs.push((successors[0], lattice, equalities.clone()));
+            outputs.push((successors[1], lattice2.unwrap(), equalities));
+        } else if let Some((&last, rest)) = successors.split_last() {
+            for &suc in rest {
+                let mut output = lattice.clone_for_branch();
+                output.apply_terminator(&terminator.kind, suc, &ctx);
+                let mut output_equalities = equalities.clone();
+                output_equalities.apply_terminator(&terminator.kind, suc);
+                outputs.push((suc, output, output_equalities));
+            }
+            // The last edge can take the state of the block
+            lattice.apply_terminator(&terminator.kind, last, &ctx);
+            equalities.apply_terminator(&terminator.kind, last);
+            outputs.push((last, lattice, equalities));
         }
+        outputs
     }
 
-    fn run_closure(&self, f: &Fn(&Statement, &L)) {
-        for (block, block_data) in self.function_mir.basic_blocks().iter_enumerated() {
-            let mut input = self.input[block].clone();
-            for stmt in block_data.statements.iter() {
-                let mut equivs = HashMap::new();
-                f(stmt, &input);
-                match stmt.kind {
-                    StatementKind::Assign(ref place, ref rvalue) => match place {
-                        Place::Base(place_base) => match place_base {
-                            PlaceBase::Local(local) => {
-                                input = input.flow_assign(*local, rvalue, &mut equivs);
-                            }
-                            _ => {}
-                        },
-                        _ => {}
-                    },
-                    _ => {} // We only really care about assignments
+    /// Dividend and divisor of a remainder recorded by `Equalities`: the locals that hold them
+    /// now, or the constants of the statement that computed it
+    fn remainder_operands(
+        &self,
+        (location, dividend, divisor): (Location, Option<Local>, Option<Local>),
+    ) -> Option<(Operand<'tcx>, Operand<'tcx>)> {
+        let mir = self.function_mir.0;
+        let stmt = &mir.basic_blocks()[location.block].statements[location.statement_index];
+        let operand = |local: Option<Local>, op: &Operand<'tcx>| match local {
+            Some(local) => Operand::Copy(Place::Base(PlaceBase::Local(local))),
+            None => op.clone(),
+        };
+        match stmt.kind {
+            StatementKind::Assign(_, ref rvalue) => match **rvalue {
+                Rvalue::BinaryOp(BinOp::Rem, ref op1, ref op2) => {
+                    Some((operand(dividend, op1), operand(divisor, op2)))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn run_closure(&self, f: &mut dyn FnMut(&Statement, &L, &Site)) {
+        // Unreached blocks have no equalities, and any will do for their bottom input
+        let stored_equalities = |block: BasicBlock| {
+            self.equalities[block]
+                .clone()
+                .unwrap_or_else(|| Equalities::new(&self.escaping))
+        };
+        if !self.config.sparse {
+            for (block, input) in self.input.iter_enumerated() {
+                self.check_block(f, block, input.clone().unwrap(), stored_equalities(block));
+            }
+            return;
+        }
+        // Recompute the inputs that were not kept. The predecessor of such a block comes before
+        // it in reverse postorder.
+        let mut pending = HashMap::new();
+        for block in cfg::reverse_postorder(self.function_mir.basic_blocks()) {
+            let (input, equalities) = if self.stored[block] {
+                (self.input[block].clone().unwrap(), stored_equalities(block))
+            } else {
+                match pending.remove(&block) {
+                    Some(input) => input,
+                    None => continue,
+                }
+            };
+            self.check_block(f, block, input.clone(), equalities.clone());
+            for (suc, output, equalities) in self.transfer(block, input, equalities) {
+                if !self.stored[suc] && !output.is_unreachable() {
+                    pending.insert(suc, (output, equalities));
                 }
             }
         }
     }
 
+    fn check_block(
+        &self,
+        f: &mut dyn FnMut(&Statement, &L, &Site),
+        block: BasicBlock,
+        mut input: L,
+        mut equalities: Equalities,
+    ) {
+        let ctx = self.context();
+        let block_data = &self.function_mir.basic_blocks()[block];
+        for (statement_index, stmt) in block_data.statements.iter().enumerate() {
+            let site = Site {
+                location: Location {
+                    block,
+                    statement_index,
+                },
+                cfg: &self.cfg,
+            };
+            f(stmt, &input, &site);
+            input.apply_statement(stmt, &equalities, &ctx);
+            equalities.apply_statement(stmt, site.location);
+        }
+    }
+
     #[allow(unused)]
     fn print_mir(&self) {
         for (idx, block_data) in self.function_mir.basic_blocks().iter().enumerate() {
@@ -301,31 +589,198 @@ impl<'tcx, L: lattice::Lattice> Analysis<'tcx, L> {
     }
 }
 
-struct CompilerCallback<
-    L: lattice::Lattice + Send + Sync,
-    F: for<'r, 's, 't0> std::ops::Fn(&'r rustc::mir::Statement<'s>, &'t0 L)
-        + 'static
-        + Send
-        + Sync,
-> {
-    f: F,
-    l: PhantomData<L>,
+/// Receives each statement with the dataflow information before it
+trait Checker<L>: Send + Sync {
+    fn check(&self, stmt: &Statement, input: &L, site: &Site);
+}
+
+/// A checker that doesn't need to know where statements are
+struct Plain<F>(F);
+
+impl<L, F: for<'r, 's, 't0> std::ops::Fn(&'r rustc::mir::Statement<'s>, &'t0 L) + Send + Sync>
+    Checker<L> for Plain<F>
+{
+    fn check(&self, stmt: &Statement, input: &L, _site: &Site) {
+        (self.0)(stmt, input)
+    }
 }
 
+struct WithSite<F>(F);
+
 impl<
-        L: lattice::Lattice + Send + Sync,
-        F: for<'r, 's, 't0> std::ops::Fn(&'r rustc::mir::Statement<'s>, &'t0 L)
-            + 'static
-            + Send
+        L,
+        F: for<'r, 's, 't0, 'u, 'v> std::ops::Fn(
+                &'r rustc::mir::Statement<'s>,
+                &'t0 L,
+                &'u Site<'v>,
+            ) + Send
             + Sync,
-    > rustc_driver::Callbacks for CompilerCallback<L, F>
+    > Checker<L> for WithSite<F>
 {
+    fn check(&self, stmt: &Statement, input: &L, site: &Site) {
+        (self.0)(stmt, input, site)
+    }
+}
+
+/// The result of analysing a function
+trait Solution<S> {
+    fn metrics(&self) -> &Metrics;
+
+    /// Set if the analysis was stopped before it converged
+    fn exceeded(&self) -> Option<Exceeded> {
+        None
+    }
+
+    /// Calls `f` on every statement with the state before it
+    fn for_each_statement(&self, f: &mut dyn FnMut(&Statement, &S, &Site));
+}
+
+impl<'tcx, L: lattice::Lattice> Solution<L> for Analysis<'tcx, L> {
+    fn metrics(&self) -> &Metrics {
+        &self.worklist.metrics
+    }
+
+    fn exceeded(&self) -> Option<Exceeded> {
+        self.exceeded
+    }
+
+    fn for_each_statement(&self, f: &mut dyn FnMut(&Statement, &L, &Site)) {
+        self.run_closure(f);
+    }
+}
+
+struct GenKillSolution<'tcx, A: GenKill> {
+    analysis: GenKillAnalysis<'tcx, A>,
+    cfg: ControlFlow,
+    exceeded: Option<Exceeded>,
+}
+
+impl<'tcx, A: GenKill> Solution<BitSet<A::Idx>> for GenKillSolution<'tcx, A> {
+    fn metrics(&self) -> &Metrics {
+        self.analysis.metrics()
+    }
+
+    fn exceeded(&self) -> Option<Exceeded> {
+        self.exceeded
+    }
+
+    fn for_each_statement(&self, f: &mut dyn FnMut(&Statement, &BitSet<A::Idx>, &Site)) {
+        self.analysis.for_each_statement(|location, stmt, set| {
+            let site = Site {
+                location,
+                cfg: &self.cfg,
+            };
+            f(stmt, set, &site);
+        });
+    }
+}
+
+/// An analysis engine, run on each function
+trait Engine {
+    /// Solutions are sent back from the worker threads, along with their states
+    type State: Send;
+
+    /// `'a` can be shorter than `'tcx`, so that engines don't need to be `'tcx`
+    fn solve<'a, 'tcx: 'a>(
+        mir: &'tcx Mir<'tcx>,
+        pointer_size: Size,
+        config: &Config,
+    ) -> Box<dyn Solution<Self::State> + Send + 'a>
+    where
+        Self: 'a;
+}
+
+struct LatticeEngine<L>(PhantomData<fn() -> L>);
+
+impl<L: lattice::Lattice + Send> Engine for LatticeEngine<L> {
+    type State = L;
+
+    fn solve<'a, 'tcx: 'a>(
+        mir: &'tcx Mir<'tcx>,
+        pointer_size: Size,
+        config: &Config,
+    ) -> Box<dyn Solution<L> + Send + 'a>
+    where
+        Self: 'a,
+    {
+        let mut analysis = Analysis::<L>::new(mir, pointer_size, config);
+        analysis.run();
+        Box::new(analysis)
+    }
+}
+
+struct GenKillEngine<A>(PhantomData<fn() -> A>);
+
+impl<A: GenKill + Send> Engine for GenKillEngine<A> {
+    type State = BitSet<A::Idx>;
+
+    fn solve<'a, 'tcx: 'a>(
+        mir: &'tcx Mir<'tcx>,
+        _pointer_size: Size,
+        config: &Config,
+    ) -> Box<dyn Solution<Self::State> + Send + 'a>
+    where
+        Self: 'a,
+    {
+        let mut analysis = GenKillAnalysis::<A>::new(mir, config.strategy);
+        let exceeded = analysis.run_within(config.max_iterations, config.timeout);
+        if exceeded.is_some() && config.on_exceeded == OnExceeded::Top {
+            analysis.give_up();
+        }
+        Box::new(GenKillSolution {
+            analysis,
+            cfg: ControlFlow::new(mir.basic_blocks()),
+            exceeded,
+        })
+    }
+}
+
+fn print_metrics(metrics: &Metrics) {
+    println!(
+        "Converged after {} iterations ({} pushes, {} duplicates, at most {} pending blocks, at most {} visits per block)",
+        metrics.iterations,
+        metrics.pushes,
+        metrics.duplicates,
+        metrics.max_len,
+        metrics.max_visits
+    );
+}
+
+struct CompilerCallback<E: Engine, C: Checker<E::State>> {
+    checker: C,
+    engine: PhantomData<fn() -> E>,
+    config: Config,
+}
+
+impl<E: Engine, C: Checker<E::State>> CompilerCallback<E, C> {
+    fn report(&self, fn_name: &str, solution: &dyn Solution<E::State>) {
+        println!("Analysing function: \"{}\"", fn_name);
+        if self.config.print_metrics {
+            print_metrics(solution.metrics());
+        }
+        if let Some(exceeded) = solution.exceeded() {
+            match self.config.on_exceeded {
+                OnExceeded::Top => println!("The analysis {}, using top states", exceeded),
+                OnExceeded::Abort => {
+                    println!("The analysis {}, skipping the function", exceeded);
+                    println!();
+                    return;
+                }
+            }
+        }
+        solution.for_each_statement(&mut |stmt, state, site| self.checker.check(stmt, state, site));
+        println!();
+    }
+}
+
+impl<E: Engine, C: Checker<E::State>> rustc_driver::Callbacks for CompilerCallback<E, C> {
     fn after_analysis(&mut self, compiler: &interface::Compiler) -> bool {
         compiler.global_ctxt().unwrap().peek_mut().enter(|tcx| {
             // let (main_id, _) = tcx.entry_fn(LOCAL_CRATE).unwrap();
             let set = tcx.mir_keys(LOCAL_CRATE);
             let mut keys = set.iter().cloned().collect::<Vec<_>>();
             keys.sort();
+            let mut functions = Vec::with_capacity(keys.len());
             for key in keys.into_iter() {
                 let module_name = key.describe_as_module(tcx);
                 let (start, end) = (
@@ -337,12 +792,33 @@ impl<
                     .skip(start)
                     .take(end - start)
                     .collect::<String>();
-                println!("Analysing function: \"{}\"", fn_name);
-                let mir = tcx.optimized_mir(key);
-                let mut analysis = Analysis::<L>::new(mir);
-                analysis.run();
-                analysis.run_closure(&self.f);
-                println!();
+                // The MIR must be built on this thread, which owns the type context
+                functions.push((fn_name, SharedMir(tcx.optimized_mir(key))));
+            }
+            let pointer_size = tcx.data_layout.pointer_size;
+
+            if self.config.threads <= 1 {
+                for (fn_name, mir) in functions.iter() {
+                    let solution = E::solve(mir.0, pointer_size, &self.config);
+                    self.report(fn_name, &*solution);
+                }
+                return;
+            }
+
+            // Solve every function on the worker threads, then report them in order here
+            let solutions = functions
+                .iter()
+                .map(|_| Mutex::new(None))
+                .collect::<Vec<_>>();
+            let config = &self.config;
+            parallel::for_each_index(functions.len(), config.threads, &|i| {
+                let SharedMir(mir) = functions[i].1;
+                let slot = &solutions[i];
+                *slot.lock().unwrap() = Some(E::solve(mir, pointer_size, config));
+            });
+            for (function, solution) in functions.iter().zip(solutions.into_iter()) {
+                let solution = solution.into_inner().unwrap().unwrap();
+                self.report(&function.0, &*solution);
             }
         });
 
@@ -360,6 +836,60 @@ pub fn run<
     target: &str,
     f: &'static F,
 ) {
+    run_with_config(target, f, Config::default())
+}
+
+pub fn run_with_config<
+    L: lattice::Lattice + Send + Sync,
+    F: for<'r, 's, 't0> std::ops::Fn(&'r rustc::mir::Statement<'s>, &'t0 L)
+        + 'static
+        + Send
+        + Sync,
+>(
+    target: &str,
+    f: &'static F,
+    config: Config,
+) {
+    run_checker::<LatticeEngine<L>, _>(target, Plain(f), config)
+}
+
+/// Like `run_with_config`, but `f` also receives the location of each statement and control flow
+/// information (dominators, post-dominators and loops) about its function
+pub fn run_with_site<
+    L: lattice::Lattice + Send + Sync,
+    F: for<'r, 's, 't0, 'u, 'v> std::ops::Fn(&'r rustc::mir::Statement<'s>, &'t0 L, &'u Site<'v>)
+        + 'static
+        + Send
+        + Sync,
+>(
+    target: &str,
+    f: &'static F,
+    config: Config,
+) {
+    run_checker::<LatticeEngine<L>, _>(target, WithSite(f), config)
+}
+
+/// Runs a gen/kill analysis instead of a `Lattice` one. `f` receives each statement with the set
+/// that flows into it (see `GenKillAnalysis::for_each_statement`).
+pub fn run_gen_kill<
+    A: GenKill + Send,
+    F: for<'r, 's, 't0, 'u, 'v> std::ops::Fn(
+            &'r rustc::mir::Statement<'s>,
+            &'t0 BitSet<A::Idx>,
+            &'u Site<'v>,
+        )
+        + 'static
+        + Send
+        + Sync,
+>(
+    target: &str,
+    f: &'static F,
+    config: Config,
+) {
+    run_checker::<GenKillEngine<A>, _>(target, WithSite(f), config)
+}
+
+fn run_checker<E: Engine, C: Checker<E::State>>(target: &str, checker: C, config: Config) {
     let exe = std::env::current_exe().unwrap();
     let exe = exe.to_str().unwrap();
     let sysroot = match std::env::var_os("RUST_SYSROOT") {
@@ -373,7 +903,7 @@ pub fn run<
     };
     // let target = std::env::args().skip(1).next().expect("Missing target source file");
 
-    let args = vec![
+    let mut args = vec![
         exe,
         target,
         "--sysroot",
@@ -385,10 +915,16 @@ pub fn run<
         "unused_mut",
         "--crate-type=lib",
     ];
+    match config.overflow {
+        Overflow::Wrapping => {}
+        Overflow::Checked => args.extend(&["-C", "overflow-checks=on"]),
+        Overflow::Panicking => args.extend(&["-C", "overflow-checks=on", "-C", "panic=abort"]),
+    }
     let args = args.into_iter().map(|x| x.to_owned()).collect::<Vec<_>>();
     let mut callback = CompilerCallback {
-        f: f,
-        l: PhantomData::<L>,
+        checker,
+        engine: PhantomData::<fn() -> E>,
+        config,
     };
     rustc_driver::run_compiler(&args[..], &mut callback, None, None).unwrap();
 }
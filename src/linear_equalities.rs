@@ -0,0 +1,465 @@
+use crate::equalities::Equalities;
+use crate::lattice::{Context, Lattice};
+use crate::relational::{self, Relational, Term, Vars};
+use rustc::mir::{
+    BasicBlock, BinOp, CastKind, Local, LocalDecl, Operand, Place, Rvalue, Statement,
+    TerminatorKind, UnOp,
+};
+use rustc_data_structures::indexed_vec::IndexVec;
+use std::sync::Arc;
+
+/// Equality `a_0 * x_0 + ... + a_n-1 * x_n-1 = b`, stored as `[a_0, ..., a_n-1, b]`. The same layout
+/// also stands for the affine expression `a_0 * x_0 + ... + a_n-1 * x_n-1 + b`.
+type Row = Vec<i128>;
+
+fn gcd(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+fn leading(row: &[i128]) -> Option<usize> {
+    row.iter().position(|&a| a != 0)
+}
+
+/// Divides a row by the gcd of its entries, and makes its leading entry positive
+fn normalize(row: &mut Row) {
+    let g = row.iter().fold(0, |g, &a| gcd(g, a.wrapping_abs() as u128));
+    if g > 1 && g <= i128::max_value() as u128 {
+        for a in row.iter_mut() {
+            *a /= g as i128;
+        }
+    }
+    if let Some(p) = leading(row) {
+        if row[p] < 0 {
+            if let Some(negated) = row.iter().map(|a| a.checked_neg()).collect::<Option<Row>>() {
+                *row = negated;
+            }
+        }
+    }
+}
+
+/// Removes column `p` from `row` with `pivot`, whose entry at `p` is not 0. `None` on overflow.
+fn eliminate(row: &[i128], pivot: &[i128], p: usize) -> Option<Row> {
+    let (k1, k2) = (pivot[p], row[p]);
+    let mut result = row
+        .iter()
+        .zip(pivot.iter())
+        .map(|(&a, &b)| k1.checked_mul(a)?.checked_sub(k2.checked_mul(b)?))
+        .collect::<Option<Row>>()?;
+    normalize(&mut result);
+    Some(result)
+}
+
+/// Adds `row` to a system in reduced row echelon form sorted by leading column, and keeps it so.
+/// Rows whose coefficients would overflow are dropped instead, which only loses equalities.
+fn insert(rows: &mut Vec<Row>, mut row: Row) {
+    for pivot in rows.iter() {
+        let p = leading(pivot).unwrap();
+        if row[p] != 0 {
+            row = match eliminate(&row, pivot, p) {
+                Some(row) => row,
+                None => return,
+            };
+        }
+    }
+    normalize(&mut row);
+    let p = match leading(&row) {
+        Some(p) => p,
+        // Implied by the others
+        None => return,
+    };
+    let mut i = 0;
+    while i < rows.len() {
+        if rows[i][p] == 0 {
+            i += 1;
+            continue;
+        }
+        match eliminate(&rows[i], &row, p) {
+            Some(reduced) => {
+                rows[i] = reduced;
+                i += 1;
+            }
+            None => {
+                rows.remove(i);
+            }
+        }
+    }
+    let position = rows
+        .iter()
+        .position(|other| leading(other) > Some(p))
+        .unwrap_or_else(|| rows.len());
+    rows.insert(position, row);
+}
+
+/// Affine equalities between the integer locals of a function (Karr's domain), such as
+/// `y == 2 * x + 1` or `i + j == n`. Equalities are those of rational numbers, and only follow
+/// arithmetic that can't wrap around (overflow checks, or operations that can't overflow).
+///
+/// The lattice has no infinite ascending chains, so it needs no widening.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinearEqualities {
+    vars: Arc<Vars>,
+    /// Independent equalities in reduced row echelon form, with coprime coefficients and positive
+    /// leading ones, which makes it canonical. `None` is bottom.
+    rows: Option<Vec<Row>>,
+}
+
+impl LinearEqualities {
+    fn new(decls: &IndexVec<Local, LocalDecl>, empty: bool) -> Self {
+        LinearEqualities {
+            vars: Arc::new(Vars::new(decls)),
+            rows: if empty { None } else { Some(Vec::new()) },
+        }
+    }
+
+    pub fn is_bottom(&self) -> bool {
+        self.rows.is_none()
+    }
+
+    /// Whether `sum(a * local) == constant` holds in all the states
+    pub fn holds(&self, coefficients: &[(Local, i128)], constant: i128) -> bool {
+        let rows = match &self.rows {
+            Some(rows) => rows,
+            None => return true,
+        };
+        let mut row = vec![0i128; self.vars.len() + 1];
+        for &(local, a) in coefficients {
+            match self.vars.get(local) {
+                Some(v) => match row[v].checked_add(a) {
+                    Some(sum) => row[v] = sum,
+                    None => return false,
+                },
+                None if a == 0 => {}
+                None => return false,
+            }
+        }
+        row[self.vars.len()] = constant;
+        for pivot in rows {
+            let p = leading(pivot).unwrap();
+            if row[p] != 0 {
+                row = match eliminate(&row, pivot, p) {
+                    Some(row) => row,
+                    None => return false,
+                };
+            }
+        }
+        leading(&row).is_none()
+    }
+
+    /// Value of `local`, if it is the same in all the states
+    pub fn constant(&self, local: Local) -> Option<i128> {
+        let (rows, v) = (self.rows.as_ref()?, self.vars.get(local)?);
+        let n = self.vars.len();
+        let row = rows.iter().find(|row| leading(row) == Some(v))?;
+        if row[v + 1..n].iter().any(|&a| a != 0) || row[n] % row[v] != 0 {
+            return None;
+        }
+        Some(row[n] / row[v])
+    }
+
+    /// Rebuilds the system from `rows`
+    fn rebuild(&mut self, rows: Vec<Row>) {
+        let n = self.vars.len();
+        let mut system = Vec::with_capacity(rows.len());
+        for row in rows {
+            insert(&mut system, row);
+        }
+        // `0 = b` for some `b != 0`
+        let inconsistent = system.last().map_or(false, |row| leading(row) == Some(n));
+        self.rows = if inconsistent { None } else { Some(system) };
+    }
+
+    fn add_row(&mut self, row: Row) {
+        if let Some(mut rows) = self.rows.take() {
+            rows.push(row);
+            self.rebuild(rows);
+        }
+    }
+
+    fn affine(&self, t: Term) -> Option<Row> {
+        let mut e = vec![0; self.vars.len() + 1];
+        match t {
+            Term::Var(v) => e[v] = 1,
+            Term::Const(k) => e[self.vars.len()] = k,
+            Term::Unknown => return None,
+        }
+        Some(e)
+    }
+
+    /// `x := e`
+    fn assign(&mut self, x: usize, e: Option<Row>) {
+        let e = match e {
+            Some(e) => e,
+            None => {
+                self.forget(x);
+                return;
+            }
+        };
+        let n = self.vars.len();
+        let a = e[x];
+        if a == 0 {
+            self.forget(x);
+            // `x - (e - d) = d`, where `d` is the constant term of `e`
+            let row = (0..=n)
+                .map(|i| {
+                    if i == x {
+                        Some(1)
+                    } else if i == n {
+                        Some(e[n])
+                    } else {
+                        e[i].checked_neg()
+                    }
+                })
+                .collect::<Option<Row>>();
+            if let Some(row) = row {
+                self.add_row(row);
+            }
+            return;
+        }
+        // `e = a * x + rest`, so the old value of `x` is `(x - rest) / a`, which replaces it in
+        // each equality multiplied by `a`
+        let rows = match self.rows.take() {
+            Some(rows) => rows,
+            None => return,
+        };
+        let substituted = rows
+            .into_iter()
+            .filter_map(|row| {
+                let r = row[x];
+                if r == 0 {
+                    return Some(row);
+                }
+                (0..=n)
+                    .map(|i| {
+                        if i == x {
+                            Some(r)
+                        } else if i == n {
+                            a.checked_mul(row[n])?.checked_add(r.checked_mul(e[n])?)
+                        } else {
+                            a.checked_mul(row[i])?.checked_sub(r.checked_mul(e[i])?)
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        self.rebuild(substituted);
+    }
+
+    fn assign_binop<'tcx>(
+        &mut self,
+        x: usize,
+        op: BinOp,
+        a: &Operand<'tcx>,
+        b: &Operand<'tcx>,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        if !ctx.arith(a).is_exact() {
+            self.forget(x);
+            return;
+        }
+        let (a, b) = (self.vars.term(a, ctx), self.vars.term(b, ctx));
+        let e = match (op, self.affine(a), self.affine(b)) {
+            (BinOp::Add, Some(ea), Some(eb)) => combine(1, &ea, 1, &eb),
+            (BinOp::Sub, Some(ea), Some(eb)) => combine(1, &ea, -1, &eb),
+            (BinOp::Mul, _, _) => match (a, b) {
+                (Term::Const(k), t) | (t, Term::Const(k)) => {
+                    self.affine(t).and_then(|e| scale(k, &e))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        self.assign(x, e);
+    }
+}
+
+/// `k1 * e1 + k2 * e2`, or `None` on overflow
+fn combine(k1: i128, e1: &[i128], k2: i128, e2: &[i128]) -> Option<Row> {
+    e1.iter()
+        .zip(e2.iter())
+        .map(|(&a, &b)| k1.checked_mul(a)?.checked_add(k2.checked_mul(b)?))
+        .collect()
+}
+
+fn scale(k: i128, e: &[i128]) -> Option<Row> {
+    e.iter().map(|&a| k.checked_mul(a)).collect()
+}
+
+impl Lattice for LinearEqualities {
+    fn bot(decls: &IndexVec<Local, LocalDecl>) -> Self {
+        LinearEqualities::new(decls, true)
+    }
+
+    fn top(decls: &IndexVec<Local, LocalDecl>) -> Self {
+        LinearEqualities::new(decls, false)
+    }
+
+    fn is_unreachable(&self) -> bool {
+        self.is_bottom()
+    }
+
+    /// Affine hull: the equalities that hold in both states are the intersection of the spaces
+    /// spanned by their rows, computed with Zassenhaus' algorithm
+    fn join(op1: &Self, op2: &Self) -> Self {
+        let (rows1, rows2) = match (&op1.rows, &op2.rows) {
+            (None, _) => return op2.clone(),
+            (_, None) => return op1.clone(),
+            (Some(rows1), Some(rows2)) => (rows1, rows2),
+        };
+        // Reducing the rows `[r1, r1]` and `[r2, 0]` leaves rows `[0, r]` where `r` is in both
+        // spaces. Dropping rows on overflow can't add anything else.
+        let width = op1.vars.len() + 1;
+        let mut system = Vec::new();
+        for row in rows1 {
+            insert(&mut system, row.iter().chain(row.iter()).cloned().collect());
+        }
+        for row in rows2 {
+            let zeros = std::iter::repeat(0).take(width);
+            insert(&mut system, row.iter().cloned().chain(zeros).collect());
+        }
+        let mut result = op1.clone();
+        result.rebuild(
+            system
+                .into_iter()
+                .filter(|row| leading(row) >= Some(width))
+                .map(|row| row[width..].to_vec())
+                .collect(),
+        );
+        result
+    }
+
+    fn apply_assign<'tcx>(
+        &mut self,
+        local: Local,
+        rvalue: &Box<Rvalue<'tcx>>,
+        _equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        relational::apply_assign(self, local, rvalue, ctx)
+    }
+
+    fn apply_branch<'tcx>(
+        &mut self,
+        on_true: &mut Self,
+        rvalue: &Box<Rvalue<'tcx>>,
+        _equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        if let Rvalue::BinaryOp(op, ref a, ref b) = **rvalue {
+            let (a, b) = (self.vars.term(a, ctx), self.vars.term(b, ctx));
+            let difference = match (self.affine(a), self.affine(b)) {
+                (Some(ea), Some(eb)) => combine(1, &ea, -1, &eb),
+                _ => None,
+            };
+            // `a - b = 0`
+            let row = difference.and_then(|mut row| {
+                let n = row.len() - 1;
+                row[n] = row[n].checked_neg()?;
+                Some(row)
+            });
+            match (op, row) {
+                (BinOp::Eq, Some(row)) => on_true.add_row(row),
+                (BinOp::Ne, Some(row)) => self.add_row(row),
+                _ => {}
+            }
+        }
+    }
+
+    fn apply_function_call(&mut self, _func: &Operand, _args: &Vec<Operand>, destination: &Place) {
+        relational::apply_function_call(self, destination)
+    }
+
+    fn apply_statement<'tcx>(
+        &mut self,
+        stmt: &Statement<'tcx>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        relational::apply_statement(self, stmt, equalities, ctx)
+    }
+
+    fn apply_assert<'tcx>(
+        &mut self,
+        on_failure: &mut Self,
+        cond: &Operand<'tcx>,
+        _expected: bool,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        relational::apply_assert(on_failure, cond, ctx)
+    }
+
+    fn apply_terminator<'tcx>(
+        &mut self,
+        kind: &TerminatorKind<'tcx>,
+        target: BasicBlock,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        relational::apply_terminator(self, kind, target, ctx)
+    }
+}
+
+impl Relational for LinearEqualities {
+    fn vars(&self) -> &Vars {
+        &self.vars
+    }
+
+    fn forget(&mut self, x: usize) {
+        let mut rows = match self.rows.take() {
+            Some(rows) => rows,
+            None => return,
+        };
+        if let Some(i) = rows.iter().position(|row| row[x] != 0) {
+            let pivot = rows.remove(i);
+            rows = rows
+                .into_iter()
+                .filter_map(|row| {
+                    if row[x] == 0 {
+                        Some(row)
+                    } else {
+                        eliminate(&row, &pivot, x)
+                    }
+                })
+                .collect();
+        }
+        self.rebuild(rows);
+    }
+
+    fn assign_term<'tcx>(&mut self, x: usize, t: Term, _ctx: &Context<'_, 'tcx>) {
+        let e = self.affine(t);
+        self.assign(x, e);
+    }
+
+    fn assign_rvalue<'tcx>(&mut self, x: usize, rvalue: &Rvalue<'tcx>, ctx: &Context<'_, 'tcx>) {
+        match *rvalue {
+            Rvalue::BinaryOp(op, ref a, ref b) | Rvalue::CheckedBinaryOp(op, ref a, ref b) => {
+                self.assign_binop(x, op, a, b, ctx)
+            }
+            Rvalue::UnaryOp(UnOp::Neg, ref op) if ctx.arith(op).is_exact() => {
+                let e = self.affine(self.vars.term(op, ctx));
+                self.assign(x, e.and_then(|e| scale(-1, &e)));
+            }
+            // Casts to a type with all the values of the original one keep the value
+            Rvalue::Cast(CastKind::Misc, ref op, ty) => {
+                let from = ctx.operand_ty(op).and_then(|ty| ctx.int_type(ty));
+                let widening = match (from, ctx.int_type(ty)) {
+                    (Some(from), Some(to)) => {
+                        (from.signed == to.signed && from.bits() <= to.bits())
+                            || (!from.signed && to.signed && from.bits() < to.bits())
+                    }
+                    _ => false,
+                };
+                let e = if widening {
+                    self.affine(self.vars.term(op, ctx))
+                } else {
+                    None
+                };
+                self.assign(x, e);
+            }
+            _ => self.forget(x),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::cfg::predecessors;
+use crate::dominators::DominatorTree;
+use rustc::mir::{BasicBlock, BasicBlockData};
+use rustc_data_structures::indexed_vec::IndexVec;
+use std::collections::HashSet;
+
+/// A natural loop: the blocks that can reach a back edge to `header` without going through it.
+/// Back edges with the same header are merged into one loop.
+#[derive(Debug, Clone)]
+pub struct Loop {
+    pub header: BasicBlock,
+    /// Blocks in the loop, including the header and the blocks of nested loops
+    pub body: Vec<BasicBlock>,
+    /// Blocks outside the loop with a predecessor inside it
+    pub exits: Vec<BasicBlock>,
+    /// Index of the innermost loop that contains this one
+    pub parent: Option<usize>,
+    /// 1 for outermost loops
+    pub depth: usize,
+}
+
+/// Natural loops of a function. Loops are sorted so that every loop comes after its parent.
+#[derive(Debug, Clone)]
+pub struct Loops {
+    pub loops: Vec<Loop>,
+    innermost: IndexVec<BasicBlock, Option<usize>>,
+}
+
+impl Loops {
+    pub fn new(blocks: &IndexVec<BasicBlock, BasicBlockData>, dominators: &DominatorTree) -> Self {
+        let preds = predecessors(blocks);
+        let mut bodies: Vec<(BasicBlock, IndexVec<BasicBlock, bool>)> = Vec::new();
+        for (block, data) in blocks.iter_enumerated() {
+            if !dominators.is_reachable(block) {
+                continue;
+            }
+            for &header in data.terminator().successors() {
+                if !dominators.dominates(header, block) {
+                    continue;
+                }
+                let position = match bodies.iter().position(|(h, _)| *h == header) {
+                    Some(position) => position,
+                    None => {
+                        let mut body = IndexVec::from_elem_n(false, blocks.len());
+                        body[header] = true;
+                        bodies.push((header, body));
+                        bodies.len() - 1
+                    }
+                };
+                // Walk backwards from the source of the back edge until the header
+                let body = &mut bodies[position].1;
+                let mut stack = vec![block];
+                while let Some(b) = stack.pop() {
+                    if body[b] {
+                        continue;
+                    }
+                    body[b] = true;
+                    stack.extend(
+                        preds[b]
+                            .iter()
+                            .filter(|&&pred| dominators.is_reachable(pred)),
+                    );
+                }
+            }
+        }
+
+        let mut loops = bodies
+            .into_iter()
+            .map(|(header, in_body)| {
+                let body = in_body
+                    .iter_enumerated()
+                    .filter(|(_, &inside)| inside)
+                    .map(|(b, _)| b)
+                    .collect::<Vec<_>>();
+                let mut exits = Vec::new();
+                for &b in body.iter() {
+                    for &suc in blocks[b].terminator().successors() {
+                        if !in_body[suc] && !exits.contains(&suc) {
+                            exits.push(suc);
+                        }
+                    }
+                }
+                Loop {
+                    header,
+                    body,
+                    exits,
+                    parent: None,
+                    depth: 1,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Natural loops with different headers are either nested or disjoint, so visiting larger
+        // loops first leaves the innermost loop of each block last
+        loops.sort_by_key(|l| (std::cmp::Reverse(l.body.len()), l.header));
+        let mut innermost = IndexVec::from_elem_n(None, blocks.len());
+        for i in 0..loops.len() {
+            let parent = innermost[loops[i].header];
+            loops[i].parent = parent;
+            loops[i].depth = parent.map_or(1, |p: usize| loops[p].depth + 1);
+            for &b in loops[i].body.iter() {
+                innermost[b] = Some(i);
+            }
+        }
+        Loops { loops, innermost }
+    }
+
+    /// The innermost loop that contains `block`
+    pub fn innermost(&self, block: BasicBlock) -> Option<&Loop> {
+        self.innermost[block].map(|i| &self.loops[i])
+    }
+
+    /// Number of loops that contain `block`
+    pub fn depth(&self, block: BasicBlock) -> usize {
+        self.innermost(block).map_or(0, |l| l.depth)
+    }
+
+    pub fn is_header(&self, block: BasicBlock) -> bool {
+        self.innermost(block).map_or(false, |l| l.header == block)
+    }
+
+    pub fn headers(&self) -> HashSet<BasicBlock> {
+        self.loops.iter().map(|l| l.header).collect()
+    }
+}
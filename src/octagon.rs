@@ -0,0 +1,555 @@
+use crate::equalities::Equalities;
+use crate::int::IntType;
+use crate::lattice::{Context, Lattice};
+use crate::relational::{self, Relational, Term, Vars};
+use rustc::mir::{
+    BasicBlock, BinOp, CastKind, Local, LocalDecl, Operand, Place, Rvalue, Statement,
+    TerminatorKind,
+};
+use rustc_data_structures::indexed_vec::IndexVec;
+use std::sync::Arc;
+
+const INF: i128 = i128::max_value();
+
+/// Sum of upper bounds. Saturating only loosens them.
+fn add(a: i128, b: i128) -> i128 {
+    if a == INF || b == INF {
+        INF
+    } else {
+        a.saturating_add(b)
+    }
+}
+
+/// Octagon over the integer locals of a function: conjunction of constraints `±x ± y <= c`, such as
+/// `idx - len <= -1` or `i - j <= 0`.
+///
+/// Each local `x` has two variables, `+x` (at index `2k`) and `-x` (at `2k + 1`). The difference
+/// bound matrix gives an upper bound to each difference of variables. States are kept closed (all
+/// the implied constraints are explicit), except after widening.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Octagon {
+    vars: Arc<Vars>,
+    /// `matrix[i * dim + j]` bounds `v_j - v_i`. `None` is bottom.
+    matrix: Option<Vec<i128>>,
+}
+
+impl Octagon {
+    fn new(decls: &IndexVec<Local, LocalDecl>, empty: bool) -> Self {
+        let vars = Vars::new(decls);
+        let dim = 2 * vars.len();
+        let matrix = if empty {
+            None
+        } else {
+            let mut matrix = vec![INF; dim * dim];
+            for i in 0..dim {
+                matrix[i * dim + i] = 0;
+            }
+            Some(matrix)
+        };
+        Octagon {
+            vars: Arc::new(vars),
+            matrix,
+        }
+    }
+
+    fn dim(&self) -> usize {
+        2 * self.vars.len()
+    }
+
+    fn var(&self, local: Local) -> Option<usize> {
+        self.vars.get(local)
+    }
+
+    pub fn is_bottom(&self) -> bool {
+        self.matrix.is_none()
+    }
+
+    /// Bound of `v_j - v_i`
+    fn get(&self, i: usize, j: usize) -> i128 {
+        match &self.matrix {
+            Some(m) => m[i * self.dim() + j],
+            None => INF,
+        }
+    }
+
+    fn var_interval(&self, v: usize) -> (i128, i128) {
+        let hi = self.get(2 * v + 1, 2 * v);
+        let lo = self.get(2 * v, 2 * v + 1);
+        (
+            if lo == INF {
+                i128::min_value()
+            } else {
+                -(lo >> 1)
+            },
+            if hi == INF { INF } else { hi >> 1 },
+        )
+    }
+
+    /// Smallest and largest values of `local`, `i128::min_value()` and `i128::max_value()` standing
+    /// for unbounded
+    pub fn interval(&self, local: Local) -> Option<(i128, i128)> {
+        self.var(local).map(|v| self.var_interval(v))
+    }
+
+    /// Upper bound of `a - b`, if any
+    pub fn max_difference(&self, a: Local, b: Local) -> Option<i128> {
+        let (a, b) = (self.var(a)?, self.var(b)?);
+        match self.get(2 * b, 2 * a) {
+            INF => None,
+            c => Some(c),
+        }
+    }
+
+    /// Makes all the constraints implied by the others explicit
+    pub fn close(&mut self) {
+        let dim = self.dim();
+        let m = match self.matrix.as_mut() {
+            Some(m) => m,
+            None => return,
+        };
+        for k in 0..dim {
+            for i in 0..dim {
+                let i_k = m[i * dim + k];
+                if i_k == INF {
+                    continue;
+                }
+                for j in 0..dim {
+                    let via = add(i_k, m[k * dim + j]);
+                    if via < m[i * dim + j] {
+                        m[i * dim + j] = via;
+                    }
+                }
+            }
+        }
+        if !tighten(m, dim) {
+            self.matrix = None;
+        }
+    }
+
+    /// Adds the constraint `v_j - v_i <= c`, and the constraints it implies
+    fn constrain(&mut self, i: usize, j: usize, c: i128) {
+        let dim = self.dim();
+        let m = match self.matrix.as_mut() {
+            Some(m) => m,
+            None => return,
+        };
+        if c >= m[i * dim + j] {
+            return;
+        }
+        // The same constraint, written with the opposite variables
+        let (bar_i, bar_j) = (i ^ 1, j ^ 1);
+        m[i * dim + j] = c;
+        m[bar_j * dim + bar_i] = m[bar_j * dim + bar_i].min(c);
+        // Paths through the new constraint
+        for a in 0..dim {
+            let (a_i, a_bar_j) = (m[a * dim + i], m[a * dim + bar_j]);
+            for b in 0..dim {
+                let via =
+                    add(add(a_i, c), m[j * dim + b]).min(add(add(a_bar_j, c), m[bar_i * dim + b]));
+                if via < m[a * dim + b] {
+                    m[a * dim + b] = via;
+                }
+            }
+        }
+        if !tighten(m, dim) {
+            self.matrix = None;
+        }
+    }
+
+    /// `x := x + c`
+    fn translate(&mut self, x: usize, c: i128) {
+        let dim = self.dim();
+        let shift = |i: usize| {
+            if i == 2 * x {
+                c
+            } else if i == 2 * x + 1 {
+                -c
+            } else {
+                0
+            }
+        };
+        if let Some(m) = self.matrix.as_mut() {
+            for i in 0..dim {
+                for j in 0..dim {
+                    let bound = &mut m[i * dim + j];
+                    if *bound != INF {
+                        *bound = bound.saturating_add(shift(j)).saturating_sub(shift(i));
+                    }
+                }
+            }
+        }
+    }
+
+    /// `lo <= x <= hi`
+    fn constrain_interval(&mut self, x: usize, lo: i128, hi: i128) {
+        if hi != INF {
+            self.constrain(2 * x + 1, 2 * x, hi.saturating_mul(2));
+        }
+        if lo != i128::min_value() {
+            self.constrain(2 * x, 2 * x + 1, 0i128.saturating_sub(lo.saturating_mul(2)));
+        }
+    }
+
+    /// `a - b <= c`
+    fn constrain_difference(&mut self, a: Term, b: Term, c: i128) {
+        match (a, b) {
+            (Term::Var(x), Term::Var(y)) if x == y => {
+                if c < 0 {
+                    self.matrix = None;
+                }
+            }
+            (Term::Var(x), Term::Var(y)) => self.constrain(2 * y, 2 * x, c),
+            (Term::Var(x), Term::Const(k)) => {
+                self.constrain_interval(x, i128::min_value(), add(c, k))
+            }
+            (Term::Const(k), Term::Var(y)) => self.constrain_interval(y, k.saturating_sub(c), INF),
+            (Term::Const(k1), Term::Const(k2)) => {
+                if k1.checked_sub(k2).map_or(false, |d| d > c) {
+                    self.matrix = None;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Restricts the state to the values where `a op b`
+    fn assume(&mut self, op: BinOp, a: Term, b: Term) {
+        match op {
+            BinOp::Lt => self.constrain_difference(a, b, -1),
+            BinOp::Le => self.constrain_difference(a, b, 0),
+            BinOp::Gt => self.constrain_difference(b, a, -1),
+            BinOp::Ge => self.constrain_difference(b, a, 0),
+            BinOp::Eq => {
+                self.constrain_difference(a, b, 0);
+                self.constrain_difference(b, a, 0);
+            }
+            BinOp::Ne => {
+                if let (Term::Const(k1), Term::Const(k2)) = (a, b) {
+                    if k1 == k2 {
+                        self.matrix = None;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn term<'tcx>(&self, op: &Operand<'tcx>, ctx: &Context<'_, 'tcx>) -> Term {
+        self.vars.term(op, ctx)
+    }
+
+    fn term_interval(&self, t: Term) -> (i128, i128) {
+        match t {
+            Term::Var(v) => self.var_interval(v),
+            Term::Const(k) => (k, k),
+            Term::Unknown => (i128::min_value(), INF),
+        }
+    }
+
+    fn var_type<'tcx>(&self, x: usize, ctx: &Context<'_, 'tcx>) -> Option<IntType> {
+        self.vars.int_type(x, ctx)
+    }
+
+    /// Whether `lo..=hi` is bounded and within the type of `x`, so that computing it can't wrap
+    /// around
+    fn fits<'tcx>(&self, x: usize, lo: i128, hi: i128, ctx: &Context<'_, 'tcx>) -> bool {
+        lo != i128::min_value()
+            && hi != INF
+            && self
+                .var_type(x, ctx)
+                .map_or(false, |ty| ty.contains(lo) && ty.contains(hi))
+    }
+
+    /// `x := t + c`. Unless the operation is `exact`, this only holds if it doesn't wrap around.
+    fn assign_sum<'tcx>(
+        &mut self,
+        x: usize,
+        t: Term,
+        c: i128,
+        exact: bool,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        let (lo, hi) = self.term_interval(t);
+        let fits = match (lo.checked_add(c), hi.checked_add(c)) {
+            (Some(lo), Some(hi)) => self.fits(x, lo, hi, ctx),
+            _ => false,
+        };
+        match (t, c.checked_neg()) {
+            (Term::Var(y), Some(_)) if y == x && (exact || fits) => self.translate(x, c),
+            (Term::Var(y), Some(minus_c)) if exact || fits => {
+                self.forget(x);
+                self.constrain(2 * y, 2 * x, c);
+                self.constrain(2 * x, 2 * y, minus_c);
+            }
+            (Term::Const(k), _) if exact || fits => match k.checked_add(c) {
+                Some(value) => {
+                    self.forget(x);
+                    self.constrain_interval(x, value, value);
+                }
+                None => self.forget(x),
+            },
+            _ => self.forget(x),
+        }
+    }
+
+    /// `x := lo..=hi`
+    fn assign_interval<'tcx>(
+        &mut self,
+        x: usize,
+        lo: i128,
+        hi: i128,
+        exact: bool,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        let fits = self.fits(x, lo, hi, ctx);
+        self.forget(x);
+        if exact || fits {
+            self.constrain_interval(x, lo, hi);
+        }
+    }
+
+    fn assign_binop<'tcx>(
+        &mut self,
+        x: usize,
+        op: BinOp,
+        a: &Operand<'tcx>,
+        b: &Operand<'tcx>,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        let exact = ctx.arith(a).is_exact();
+        let (a, b) = (self.term(a, ctx), self.term(b, ctx));
+        match (op, a, b) {
+            (BinOp::Add, t, Term::Const(k)) | (BinOp::Add, Term::Const(k), t) => {
+                self.assign_sum(x, t, k, exact, ctx)
+            }
+            (BinOp::Sub, t, Term::Const(k)) if k != i128::min_value() => {
+                self.assign_sum(x, t, -k, exact, ctx)
+            }
+            // The octagon bounds sums and differences of two locals
+            (BinOp::Add, Term::Var(y), Term::Var(z)) => {
+                let hi = self.get(2 * z + 1, 2 * y);
+                let lo = self.get(2 * z, 2 * y + 1);
+                self.assign_interval(x, negate_bound(lo), hi, exact, ctx);
+            }
+            (BinOp::Sub, Term::Var(y), Term::Var(z)) => {
+                let hi = self.get(2 * z, 2 * y);
+                let lo = self.get(2 * y, 2 * z);
+                self.assign_interval(x, negate_bound(lo), hi, exact, ctx);
+            }
+            (BinOp::Sub, Term::Const(k), Term::Var(y)) => {
+                let (lo, hi) = self.var_interval(y);
+                let min = if hi == INF {
+                    i128::min_value()
+                } else {
+                    k.saturating_sub(hi)
+                };
+                let max = if lo == i128::min_value() {
+                    INF
+                } else {
+                    k.saturating_sub(lo)
+                };
+                self.assign_interval(x, min, max, exact, ctx);
+            }
+            _ => self.forget(x),
+        }
+    }
+
+    /// Adds the bounds of the type of `x`
+    fn restrict_to_type<'tcx>(&mut self, x: usize, ctx: &Context<'_, 'tcx>) {
+        if let Some(ty) = self.var_type(x, ctx) {
+            self.constrain_interval(x, ty.min(), ty.max());
+        }
+    }
+}
+
+/// Lower bound of `x`, for an upper bound of `-x`
+fn negate_bound(bound: i128) -> i128 {
+    if bound == INF {
+        i128::min_value()
+    } else {
+        -bound
+    }
+}
+
+/// Integer tightening and strengthening of a closed matrix (`v_j - v_i <= (m[i][-i] + m[-j][j]) /
+/// 2`). Returns false if the octagon is empty.
+fn tighten(m: &mut [i128], dim: usize) -> bool {
+    for i in 0..dim {
+        let bound = &mut m[i * dim + (i ^ 1)];
+        if *bound != INF {
+            // 2x <= c means 2x <= 2 * floor(c / 2) for an integer x
+            *bound = (*bound >> 1) * 2;
+        }
+    }
+    for i in 0..dim {
+        let i_bar = m[i * dim + (i ^ 1)];
+        if i_bar == INF {
+            continue;
+        }
+        for j in 0..dim {
+            let sum = add(i_bar, m[(j ^ 1) * dim + j]);
+            if sum != INF && sum >> 1 < m[i * dim + j] {
+                m[i * dim + j] = sum >> 1;
+            }
+        }
+    }
+    (0..dim).all(|i| m[i * dim + i] >= 0)
+}
+
+impl Lattice for Octagon {
+    fn bot(decls: &IndexVec<Local, LocalDecl>) -> Self {
+        Octagon::new(decls, true)
+    }
+
+    fn top(decls: &IndexVec<Local, LocalDecl>) -> Self {
+        Octagon::new(decls, false)
+    }
+
+    fn is_unreachable(&self) -> bool {
+        self.is_bottom()
+    }
+
+    fn join(op1: &Self, op2: &Self) -> Self {
+        match (&op1.matrix, &op2.matrix) {
+            (None, _) => op2.clone(),
+            (_, None) => op1.clone(),
+            (Some(_), Some(_)) => {
+                // Widened states are not closed, and the join is only exact on closed states
+                let (mut op1, mut op2) = (op1.clone(), op2.clone());
+                op1.close();
+                op2.close();
+                match (op1.matrix.as_mut(), op2.matrix.as_ref()) {
+                    (Some(m1), Some(m2)) => {
+                        for (b1, b2) in m1.iter_mut().zip(m2.iter()) {
+                            *b1 = (*b1).max(*b2);
+                        }
+                        op1
+                    }
+                    _ => Self::join(&op1, &op2),
+                }
+            }
+        }
+    }
+
+    /// Drops the constraints that don't hold anymore. The result is not closed, since closing it
+    /// could prevent termination.
+    fn widen<'tcx>(op1: &Self, op2: &Self, _ctx: &Context<'_, 'tcx>) -> Self {
+        match (&op1.matrix, &op2.matrix) {
+            (None, _) => op2.clone(),
+            (_, None) => op1.clone(),
+            (Some(m1), Some(m2)) => Octagon {
+                vars: op1.vars.clone(),
+                matrix: Some(
+                    m1.iter()
+                        .zip(m2.iter())
+                        .map(|(&b1, &b2)| if b2 <= b1 { b1 } else { INF })
+                        .collect(),
+                ),
+            },
+        }
+    }
+
+    fn apply_assign<'tcx>(
+        &mut self,
+        local: Local,
+        rvalue: &Box<Rvalue<'tcx>>,
+        _equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        relational::apply_assign(self, local, rvalue, ctx)
+    }
+
+    fn apply_branch<'tcx>(
+        &mut self,
+        on_true: &mut Self,
+        rvalue: &Box<Rvalue<'tcx>>,
+        _equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        if let Rvalue::BinaryOp(op, ref a, ref b) = **rvalue {
+            let (a, b) = (self.term(a, ctx), self.term(b, ctx));
+            let negated = match op {
+                BinOp::Lt => BinOp::Ge,
+                BinOp::Le => BinOp::Gt,
+                BinOp::Gt => BinOp::Le,
+                BinOp::Ge => BinOp::Lt,
+                BinOp::Eq => BinOp::Ne,
+                BinOp::Ne => BinOp::Eq,
+                _ => return,
+            };
+            on_true.assume(op, a, b);
+            self.assume(negated, a, b);
+        }
+    }
+
+    fn apply_function_call(&mut self, _func: &Operand, _args: &Vec<Operand>, destination: &Place) {
+        relational::apply_function_call(self, destination)
+    }
+
+    fn apply_statement<'tcx>(
+        &mut self,
+        stmt: &Statement<'tcx>,
+        equalities: &Equalities,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        relational::apply_statement(self, stmt, equalities, ctx)
+    }
+
+    fn apply_assert<'tcx>(
+        &mut self,
+        on_failure: &mut Self,
+        cond: &Operand<'tcx>,
+        _expected: bool,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        relational::apply_assert(on_failure, cond, ctx)
+    }
+
+    fn apply_terminator<'tcx>(
+        &mut self,
+        kind: &TerminatorKind<'tcx>,
+        target: BasicBlock,
+        ctx: &Context<'_, 'tcx>,
+    ) {
+        relational::apply_terminator(self, kind, target, ctx)
+    }
+}
+
+impl Relational for Octagon {
+    fn vars(&self) -> &Vars {
+        &self.vars
+    }
+
+    fn forget(&mut self, x: usize) {
+        let dim = self.dim();
+        if let Some(m) = self.matrix.as_mut() {
+            for i in 2 * x..2 * x + 2 {
+                for j in 0..dim {
+                    m[i * dim + j] = INF;
+                    m[j * dim + i] = INF;
+                }
+                m[i * dim + i] = 0;
+            }
+        }
+    }
+
+    fn assign_term<'tcx>(&mut self, x: usize, t: Term, ctx: &Context<'_, 'tcx>) {
+        self.assign_sum(x, t, 0, true, ctx);
+        self.restrict_to_type(x, ctx);
+    }
+
+    fn assign_rvalue<'tcx>(&mut self, x: usize, rvalue: &Rvalue<'tcx>, ctx: &Context<'_, 'tcx>) {
+        match *rvalue {
+            Rvalue::BinaryOp(op, ref a, ref b) | Rvalue::CheckedBinaryOp(op, ref a, ref b) => {
+                self.assign_binop(x, op, a, b, ctx)
+            }
+            // The value is unchanged if it fits in the new type
+            Rvalue::Cast(CastKind::Misc, ref op, _) => {
+                let t = self.term(op, ctx);
+                self.assign_sum(x, t, 0, false, ctx);
+            }
+            _ => self.forget(x),
+        }
+        self.restrict_to_type(x, ctx);
+    }
+}
@@ -0,0 +1,106 @@
+use rustc::mir::Mir;
+use std::any::Any;
+use std::io;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+/// The MIR of a function, shared with the worker threads.
+///
+/// The MIR isn't `Sync`: it holds types interned by the compiler and caches behind `RefCell`s. The
+/// analyses only read the MIR and the types it points to (they don't use the caches, the type
+/// context or anything that interns), and the compiler doesn't run while the worker threads do
+/// because the main thread waits for them. This is the only value that crosses threads without the
+/// compiler checking it, and it must only be used under those conditions.
+#[derive(Clone, Copy)]
+pub(crate) struct SharedMir<'tcx>(pub &'tcx Mir<'tcx>);
+
+unsafe impl<'tcx> Send for SharedMir<'tcx> {}
+unsafe impl<'tcx> Sync for SharedMir<'tcx> {}
+
+impl<'tcx> Deref for SharedMir<'tcx> {
+    type Target = Mir<'tcx>;
+
+    fn deref(&self) -> &Mir<'tcx> {
+        self.0
+    }
+}
+
+/// Threads that may borrow anything that outlives `'env`, see `scope`
+struct Scope<'env> {
+    handles: Vec<thread::JoinHandle<()>>,
+    /// Makes `'env` invariant, so that a scope can't be used with a shorter lifetime
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'env> Scope<'env> {
+    fn spawn(&mut self, job: &'env (dyn Fn() + Sync)) -> io::Result<()> {
+        // Safety: the thread is joined when the scope is dropped, which `scope` does before
+        // returning or while unwinding. The scope can't outlive that call, since only a borrow of
+        // it is given out, so neither can the thread.
+        let job: &'static (dyn Fn() + Sync) = unsafe { std::mem::transmute(job) };
+        let handle = thread::Builder::new().spawn(job)?;
+        self.handles.push(handle);
+        Ok(())
+    }
+
+    fn join(&mut self) -> Option<Box<dyn Any + Send>> {
+        let mut panicked = None;
+        for handle in self.handles.drain(..) {
+            if let Err(payload) = handle.join() {
+                panicked = Some(payload);
+            }
+        }
+        panicked
+    }
+}
+
+impl<'env> Drop for Scope<'env> {
+    fn drop(&mut self) {
+        // The threads are still running if `f` panicked. Their own panics are dropped, since
+        // this one is already unwinding.
+        self.join();
+    }
+}
+
+/// Calls `f` with a scope for spawning threads, and joins them all before returning. A panic in
+/// one of the threads is propagated once they have all stopped.
+fn scope<'env, R>(f: impl FnOnce(&mut Scope<'env>) -> R) -> R {
+    let mut scope = Scope {
+        handles: Vec::new(),
+        env: PhantomData,
+    };
+    let result = f(&mut scope);
+    if let Some(payload) = scope.join() {
+        panic::resume_unwind(payload);
+    }
+    result
+}
+
+/// Calls `job` with every index in `0..count`, using up to `threads` threads. Returns once every
+/// call has finished. A panic in `job` is propagated after all threads have stopped.
+pub(crate) fn for_each_index(count: usize, threads: usize, job: &(dyn Fn(usize) + Sync)) {
+    let next = AtomicUsize::new(0);
+    let work = || loop {
+        let i = next.fetch_add(1, Ordering::SeqCst);
+        if i >= count {
+            break;
+        }
+        job(i);
+    };
+    scope(|scope| {
+        let mut spawned = 0;
+        for _ in 0..threads.min(count) {
+            match scope.spawn(&work) {
+                Ok(()) => spawned += 1,
+                Err(_) => break,
+            }
+        }
+        // Do the work here if no thread could be spawned
+        if spawned == 0 {
+            work();
+        }
+    });
+}
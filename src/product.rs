@@ -0,0 +1,216 @@
+use crate::congruence::Congruence;
+use crate::interval::Interval;
+use crate::lattice::{Arith, SimpleLattice, WideningHints};
+use rustc::mir::interpret::ConstValue;
+use rustc::mir::{AggregateKind, BinOp, CastKind, Place, UnOp};
+use rustc::ty::layout::Size;
+use rustc::ty::TyKind;
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Lets each component of a `Product` sharpen the other
+pub trait Reduction<A, B> {
+    /// Must not lose values: the pair of results must describe the same values as `(a, b)`.
+    fn reduce(a: A, b: B) -> (A, B);
+}
+
+/// Reduction that leaves both components as they are
+pub struct NoReduction;
+
+impl<A, B> Reduction<A, B> for NoReduction {
+    fn reduce(a: A, b: B) -> (A, B) {
+        (a, b)
+    }
+}
+
+/// Reduced product of two domains: a value is described by both components at once. `R` is
+/// applied after every transfer function.
+pub struct Product<A, B, R = NoReduction> {
+    pub first: A,
+    pub second: B,
+    reduction: PhantomData<fn() -> R>,
+}
+
+impl<A: SimpleLattice, B: SimpleLattice, R: Reduction<A, B>> Product<A, B, R> {
+    /// Reduced pair of `first` and `second`. The product is bottom if either of them is.
+    pub fn new(first: A, second: B) -> Self {
+        let (first, second) = if first == A::bot() || second == B::bot() {
+            (A::bot(), B::bot())
+        } else {
+            R::reduce(first, second)
+        };
+        Product {
+            first,
+            second,
+            reduction: PhantomData,
+        }
+    }
+
+    fn pair(a: (A, A), b: (B, B)) -> (Self, Self) {
+        (Self::new(a.0, b.0), Self::new(a.1, b.1))
+    }
+}
+
+// Implemented by hand, since deriving them would require `R` to implement them too
+
+impl<A: Clone, B: Clone, R> Clone for Product<A, B, R> {
+    fn clone(&self) -> Self {
+        Product {
+            first: self.first.clone(),
+            second: self.second.clone(),
+            reduction: PhantomData,
+        }
+    }
+}
+
+impl<A: Copy, B: Copy, R> Copy for Product<A, B, R> {}
+
+impl<A: PartialEq, B: PartialEq, R> PartialEq for Product<A, B, R> {
+    fn eq(&self, other: &Self) -> bool {
+        self.first == other.first && self.second == other.second
+    }
+}
+
+impl<A: Eq, B: Eq, R> Eq for Product<A, B, R> {}
+
+impl<A: fmt::Debug, B: fmt::Debug, R> fmt::Debug for Product<A, B, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({:?}, {:?})", self.first, self.second)
+    }
+}
+
+impl<A: SimpleLattice, B: SimpleLattice, R: Reduction<A, B>> SimpleLattice for Product<A, B, R> {
+    fn applies(ty: &TyKind) -> bool {
+        A::applies(ty) && B::applies(ty)
+    }
+
+    fn bot() -> Self {
+        Self::new(A::bot(), B::bot())
+    }
+
+    fn top() -> Self {
+        Self::new(A::top(), B::top())
+    }
+
+    fn join(op1: &Self, op2: &Self) -> Self {
+        Self::new(
+            A::join(&op1.first, &op2.first),
+            B::join(&op1.second, &op2.second),
+        )
+    }
+
+    fn alpha(ty: &TyKind, size: Size, a: ConstValue) -> Self {
+        Self::new(A::alpha(ty, size, a), B::alpha(ty, size, a))
+    }
+
+    fn flow_binop(op: &BinOp, arg1: &Self, arg2: &Self, arith: &Arith) -> Self {
+        Self::new(
+            A::flow_binop(op, &arg1.first, &arg2.first, arith),
+            B::flow_binop(op, &arg1.second, &arg2.second, arith),
+        )
+    }
+
+    fn flow_unop(op: &UnOp, arg: &Self, arith: &Arith) -> Self {
+        Self::new(
+            A::flow_unop(op, &arg.first, arith),
+            B::flow_unop(op, &arg.second, arith),
+        )
+    }
+
+    fn flow_cond_true(op: &BinOp, arg1: &Self, arg2: &Self) -> (Self, Self) {
+        Self::pair(
+            A::flow_cond_true(op, &arg1.first, &arg2.first),
+            B::flow_cond_true(op, &arg1.second, &arg2.second),
+        )
+    }
+
+    fn flow_cond_false(op: &BinOp, arg1: &Self, arg2: &Self) -> (Self, Self) {
+        Self::pair(
+            A::flow_cond_false(op, &arg1.first, &arg2.first),
+            B::flow_cond_false(op, &arg1.second, &arg2.second),
+        )
+    }
+
+    fn flow_cast(kind: &CastKind, from_ty: &TyKind, to_ty: &TyKind, arg: &Self) -> Self {
+        Self::new(
+            A::flow_cast(kind, from_ty, to_ty, &arg.first),
+            B::flow_cast(kind, from_ty, to_ty, &arg.second),
+        )
+    }
+
+    fn flow_len(place: &Place) -> Self {
+        Self::new(A::flow_len(place), B::flow_len(place))
+    }
+
+    fn flow_aggregate(kind: &AggregateKind, args: &[Self]) -> Self {
+        let firsts = args.iter().map(|arg| arg.first).collect::<Vec<_>>();
+        let seconds = args.iter().map(|arg| arg.second).collect::<Vec<_>>();
+        Self::new(
+            A::flow_aggregate(kind, &firsts),
+            B::flow_aggregate(kind, &seconds),
+        )
+    }
+
+    fn flow_discriminant(place: &Place) -> Self {
+        Self::new(A::flow_discriminant(place), B::flow_discriminant(place))
+    }
+
+    fn flow_rem_cond(dividend: &Self, divisor: &Self, remainder: &Self) -> Self {
+        Self::new(
+            A::flow_rem_cond(&dividend.first, &divisor.first, &remainder.first),
+            B::flow_rem_cond(&dividend.second, &divisor.second, &remainder.second),
+        )
+    }
+
+    fn widen(op1: &Self, op2: &Self, hints: &WideningHints) -> Self {
+        // Reducing a widened value could undo the widening and prevent termination
+        Product {
+            first: A::widen(&op1.first, &op2.first, hints),
+            second: B::widen(&op1.second, &op2.second, hints),
+            reduction: PhantomData,
+        }
+    }
+}
+
+/// Moves the bounds of an interval to the closest values with the right congruence, and turns
+/// both into constants when only one value is left
+pub struct IntervalCongruence;
+
+impl Reduction<Interval, Congruence> for IntervalCongruence {
+    fn reduce(interval: Interval, congruence: Congruence) -> (Interval, Congruence) {
+        let (lo, hi, modulus, residue) = match (interval, congruence) {
+            (Interval::Range(lo, hi), Congruence::Mod { modulus, residue }) => {
+                (lo, hi, modulus, residue)
+            }
+            _ => return (interval, congruence),
+        };
+        if modulus == 0 {
+            return if interval.contains(residue) {
+                (Interval::constant(residue), congruence)
+            } else {
+                (Interval::Bottom, Congruence::Bottom)
+            };
+        }
+        // Distance from `from` up to the next value with the right residue. Infinite bounds
+        // stay as they are.
+        let distance = |from: i128, to: i128| {
+            let d = to.checked_sub(from)? % modulus;
+            Some(if d < 0 { d + modulus } else { d })
+        };
+        let lo = match distance(lo, residue) {
+            Some(d) if lo != i128::min_value() => lo.checked_add(d).unwrap_or(lo),
+            _ => lo,
+        };
+        let hi = match distance(residue, hi) {
+            Some(d) if hi != i128::max_value() => hi.checked_sub(d).unwrap_or(hi),
+            _ => hi,
+        };
+        match Interval::new(lo, hi) {
+            Interval::Bottom => (Interval::Bottom, Congruence::Bottom),
+            interval => match interval.as_constant() {
+                Some(x) => (interval, Congruence::constant(x)),
+                None => (interval, congruence),
+            },
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use crate::equalities::Equalities;
+use crate::int::{ConstInt, IntType};
+use crate::lattice::{apply_edge, base_local, Context, Lattice, Overflow};
+use rustc::mir::{
+    BasicBlock, Local, LocalDecl, Operand, Place, PlaceBase, ProjectionElem, Rvalue, Statement,
+    StatementKind, TerminatorKind,
+};
+use rustc::ty::layout::Size;
+use rustc::ty::{Ty, TyKind};
+use rustc_data_structures::indexed_vec::IndexVec;
+
+/// Integer type of a local that relational domains track: an integer, or the `(T, bool)` result
+/// of a checked operation, tracked as its field 0
+fn tracked_type(ty: Ty<'_>) -> Option<Ty<'_>> {
+    match &ty.sty {
+        TyKind::Int(_) | TyKind::Uint(_) => Some(ty),
+        TyKind::Tuple(tys) if tys.len() == 2 && tys[1].sty == TyKind::Bool => match tys[0].sty {
+            TyKind::Int(_) | TyKind::Uint(_) => Some(tys[0]),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Numbering of the locals that relational domains track
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Vars {
+    index: IndexVec<Local, Option<usize>>,
+    locals: Vec<Local>,
+}
+
+impl Vars {
+    pub(crate) fn new(decls: &IndexVec<Local, LocalDecl>) -> Self {
+        let mut locals = Vec::new();
+        let index = decls
+            .iter_enumerated()
+            .map(|(local, decl)| {
+                tracked_type(decl.ty).map(|_| {
+                    locals.push(local);
+                    locals.len() - 1
+                })
+            })
+            .collect();
+        Vars { index, locals }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.locals.len()
+    }
+
+    pub(crate) fn get(&self, local: Local) -> Option<usize> {
+        self.index.get(local).cloned().and_then(|v| v)
+    }
+
+    /// Variable of `local`, unless it may be written through a pointer. Such locals are never
+    /// constrained, since any write through a pointer would have to forget them.
+    pub(crate) fn tracked<'tcx>(&self, local: Local, ctx: &Context<'_, 'tcx>) -> Option<usize> {
+        if ctx.escaping[local] {
+            None
+        } else {
+            self.get(local)
+        }
+    }
+
+    pub(crate) fn int_type<'tcx>(&self, v: usize, ctx: &Context<'_, 'tcx>) -> Option<IntType> {
+        tracked_type(ctx.decls[self.locals[v]].ty).and_then(|ty| ctx.int_type(ty))
+    }
+
+    pub(crate) fn term<'tcx>(&self, op: &Operand<'tcx>, ctx: &Context<'_, 'tcx>) -> Term {
+        let place = match op {
+            Operand::Copy(place) | Operand::Move(place) => place,
+            Operand::Constant(constant) => {
+                let size = ctx.int_type(constant.ty).map_or(Size::ZERO, |ty| ty.size);
+                return ConstInt::decode(&constant.ty.sty, size, constant.literal.val)
+                    .and_then(ConstInt::to_i128)
+                    .map_or(Term::Unknown, Term::Const);
+            }
+        };
+        let local = match place {
+            Place::Base(PlaceBase::Local(local)) => Some(*local),
+            // Field 0 of the result of a checked operation
+            Place::Projection(proj) => match (&proj.base, &proj.elem) {
+                (Place::Base(PlaceBase::Local(local)), ProjectionElem::Field(field, _))
+                    if field.index() == 0 =>
+                {
+                    Some(*local)
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        local
+            .and_then(|local| self.tracked(local, ctx))
+            .map_or(Term::Unknown, Term::Var)
+    }
+}
+
+/// Operand of a transfer function
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Term {
+    Var(usize),
+    Const(i128),
+    Unknown,
+}
+
+/// Relational domain over the locals numbered by `Vars`. The functions of this module implement
+/// the parts of `Lattice` that only forget or assign variables on top of it.
+pub(crate) trait Relational: Lattice {
+    fn vars(&self) -> &Vars;
+    /// Removes all the constraints on `x`
+    fn forget(&mut self, x: usize);
+    /// `x := t`
+    fn assign_term<'tcx>(&mut self, x: usize, t: Term, ctx: &Context<'_, 'tcx>);
+    /// `x := rvalue`, for any rvalue but a `Use`
+    fn assign_rvalue<'tcx>(&mut self, x: usize, rvalue: &Rvalue<'tcx>, ctx: &Context<'_, 'tcx>);
+}
+
+pub(crate) fn forget_place<R: Relational>(state: &mut R, place: &Place) {
+    if let Some(x) = base_local(place).and_then(|local| state.vars().get(local)) {
+        state.forget(x);
+    }
+}
+
+pub(crate) fn apply_assign<'tcx, R: Relational>(
+    state: &mut R,
+    local: Local,
+    rvalue: &Rvalue<'tcx>,
+    ctx: &Context<'_, 'tcx>,
+) {
+    let x = match state.vars().tracked(local, ctx) {
+        Some(x) => x,
+        None => return,
+    };
+    match *rvalue {
+        Rvalue::Use(ref op) => {
+            let t = state.vars().term(op, ctx);
+            state.assign_term(x, t, ctx);
+        }
+        _ => state.assign_rvalue(x, rvalue, ctx),
+    }
+}
+
+pub(crate) fn apply_function_call<R: Relational>(state: &mut R, destination: &Place) {
+    if let Place::Base(PlaceBase::Local(local)) = destination {
+        if let Some(x) = state.vars().get(*local) {
+            state.forget(x);
+        }
+    }
+}
+
+pub(crate) fn apply_statement<'tcx, R: Relational>(
+    state: &mut R,
+    stmt: &Statement<'tcx>,
+    equalities: &Equalities,
+    ctx: &Context<'_, 'tcx>,
+) {
+    match stmt.kind {
+        StatementKind::Assign(Place::Base(PlaceBase::Local(local)), ref rvalue) => {
+            state.apply_assign(local, rvalue, equalities, ctx)
+        }
+        StatementKind::Assign(ref place, _) | StatementKind::SetDiscriminant { ref place, .. } => {
+            forget_place(state, place)
+        }
+        StatementKind::InlineAsm(ref asm) => {
+            for place in asm.outputs.iter() {
+                forget_place(state, place);
+            }
+        }
+        StatementKind::StorageLive(local) | StatementKind::StorageDead(local) => {
+            if let Some(x) = state.vars().get(local) {
+                state.forget(x);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub(crate) fn apply_assert<'tcx, R: Relational>(
+    on_failure: &mut R,
+    cond: &Operand<'tcx>,
+    ctx: &Context<'_, 'tcx>,
+) {
+    // If the overflow check of a checked operation fails, its result has wrapped around
+    if ctx.overflow != Overflow::Checked {
+        return;
+    }
+    if let Operand::Copy(Place::Projection(proj)) | Operand::Move(Place::Projection(proj)) = cond {
+        if let (Place::Base(PlaceBase::Local(local)), ProjectionElem::Field(field, _)) =
+            (&proj.base, &proj.elem)
+        {
+            if let (1, Some(x)) = (field.index(), on_failure.vars().get(*local)) {
+                on_failure.forget(x);
+            }
+        }
+    }
+}
+
+pub(crate) fn apply_terminator<'tcx, R: Relational>(
+    state: &mut R,
+    kind: &TerminatorKind<'tcx>,
+    target: BasicBlock,
+    ctx: &Context<'_, 'tcx>,
+) {
+    match *kind {
+        // The new value is written even if dropping the old one panics
+        TerminatorKind::DropAndReplace {
+            location: Place::Base(PlaceBase::Local(local)),
+            ref value,
+            ..
+        } => {
+            if let Some(x) = state.vars().tracked(local, ctx) {
+                let t = state.vars().term(value, ctx);
+                state.assign_term(x, t, ctx);
+            }
+        }
+        _ => apply_edge(state, kind, target, ctx),
+    }
+}
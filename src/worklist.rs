@@ -0,0 +1,154 @@
+use crate::block::Block;
+use crate::cfg::strongly_connected_components;
+use rustc::mir::{BasicBlock, BasicBlockData};
+use rustc_data_structures::indexed_vec::IndexVec;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::hash::BuildHasher;
+
+/// Order in which the engine processes pending blocks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Lowest reverse postorder index first
+    ReversePostorder,
+    /// First in, first out
+    Fifo,
+    /// Last in, first out
+    Lifo,
+    /// Strongly connected components in topological order, each one in reverse postorder.
+    /// A loop is stabilised before the code after it is visited.
+    Scc,
+    /// A user-supplied queue
+    Custom(fn() -> Box<dyn Queue>),
+    /// Not a worklist: components of a weak topological ordering are iterated recursively until
+    /// their heads stabilise, and widening is done at the heads. Domains with widening should use
+    /// this.
+    WeakTopological,
+}
+
+/// The pending blocks of a worklist. Implementations don't need to deduplicate blocks. They are
+/// `Send` because analyses may run on worker threads (see `Config::threads`).
+pub trait Queue: Send {
+    /// `priority` is lower for blocks that should (ideally) be processed first
+    fn push(&mut self, block: BasicBlock, priority: usize);
+    fn pop(&mut self) -> Option<BasicBlock>;
+}
+
+impl Queue for BinaryHeap<Block> {
+    fn push(&mut self, block: BasicBlock, priority: usize) {
+        BinaryHeap::push(self, Block::new(block, priority));
+    }
+
+    fn pop(&mut self) -> Option<BasicBlock> {
+        BinaryHeap::pop(self).map(|block| block.id)
+    }
+}
+
+impl Queue for VecDeque<BasicBlock> {
+    fn push(&mut self, block: BasicBlock, _priority: usize) {
+        self.push_back(block);
+    }
+
+    fn pop(&mut self) -> Option<BasicBlock> {
+        self.pop_front()
+    }
+}
+
+impl Queue for Vec<BasicBlock> {
+    fn push(&mut self, block: BasicBlock, _priority: usize) {
+        Vec::push(self, block);
+    }
+
+    fn pop(&mut self) -> Option<BasicBlock> {
+        Vec::pop(self)
+    }
+}
+
+/// Counters about the convergence of an analysis
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// Blocks popped from the worklist (i.e. transfer function applications)
+    pub iterations: usize,
+    /// Calls to push, including those for blocks that were already pending
+    pub pushes: usize,
+    /// Pushes ignored because the block was already pending
+    pub duplicates: usize,
+    /// Largest number of pending blocks at any time
+    pub max_len: usize,
+    /// Largest number of times a single block was processed
+    pub max_visits: usize,
+}
+
+/// A worklist that holds each block at most once
+pub struct Worklist {
+    queue: Box<dyn Queue>,
+    queued: IndexVec<BasicBlock, bool>,
+    visits: IndexVec<BasicBlock, usize>,
+    len: usize,
+    pub metrics: Metrics,
+}
+
+impl Worklist {
+    pub fn new(strategy: Strategy, num_blocks: usize) -> Self {
+        let queue: Box<dyn Queue> = match strategy {
+            Strategy::ReversePostorder | Strategy::Scc | Strategy::WeakTopological => {
+                Box::new(BinaryHeap::<Block>::new())
+            }
+            Strategy::Fifo => Box::new(VecDeque::new()),
+            Strategy::Lifo => Box::new(Vec::new()),
+            Strategy::Custom(new_queue) => new_queue(),
+        };
+        Self {
+            queue,
+            queued: IndexVec::from_elem_n(false, num_blocks),
+            visits: IndexVec::from_elem_n(0, num_blocks),
+            len: 0,
+            metrics: Metrics::default(),
+        }
+    }
+
+    pub fn push(&mut self, block: BasicBlock, priority: usize) {
+        self.metrics.pushes += 1;
+        if self.queued[block] {
+            self.metrics.duplicates += 1;
+            return;
+        }
+        self.queued[block] = true;
+        self.queue.push(block, priority);
+        self.len += 1;
+        self.metrics.max_len = self.metrics.max_len.max(self.len);
+    }
+
+    pub fn pop(&mut self) -> Option<BasicBlock> {
+        let block = self.queue.pop()?;
+        self.queued[block] = false;
+        self.len -= 1;
+        self.visits[block] += 1;
+        self.metrics.iterations += 1;
+        self.metrics.max_visits = self.metrics.max_visits.max(self.visits[block]);
+        Some(block)
+    }
+}
+
+/// Renumbers the blocks in `rpo` (which maps each reachable block to its reverse postorder index)
+/// so that blocks are sorted by strongly connected component first, in topological order, and then
+/// by reverse postorder.
+pub fn scc_priorities<S: BuildHasher>(
+    blocks: &IndexVec<BasicBlock, BasicBlockData>,
+    rpo: &HashMap<BasicBlock, usize, S>,
+) -> HashMap<BasicBlock, usize> {
+    let all = IndexVec::from_elem_n(true, blocks.len());
+    let components = strongly_connected_components(blocks, &all);
+    let count = components
+        .iter()
+        .filter_map(|c| *c)
+        .max()
+        .map_or(0, |c| c + 1);
+    let mut sorted = rpo.iter().map(|(&b, &i)| (b, i)).collect::<Vec<_>>();
+    // Tarjan's algorithm finds the components in reverse topological order
+    sorted.sort_by_key(|&(b, i)| (count - components[b].unwrap_or(0), i));
+    sorted
+        .into_iter()
+        .enumerate()
+        .map(|(priority, (b, _))| (b, priority))
+        .collect()
+}
@@ -0,0 +1,96 @@
+use crate::cfg::strongly_connected_components;
+use rustc::mir::{BasicBlock, BasicBlockData};
+use rustc_data_structures::indexed_vec::IndexVec;
+use std::collections::{HashMap, HashSet};
+
+/// An element of a weak topological ordering
+#[derive(Debug, Clone)]
+pub enum Component {
+    Vertex(BasicBlock),
+    /// A loop. It is iterated until the input of `head` (its widening point) stabilises.
+    Cycle {
+        head: BasicBlock,
+        body: Vec<Component>,
+    },
+}
+
+/// Weak topological ordering of the blocks of a function (Bourdoncle, 1993). It is computed by
+/// recursively decomposing the control flow graph into strongly connected components: the head of
+/// each component is removed and the rest is decomposed again.
+#[derive(Debug, Clone)]
+pub struct Wto {
+    pub components: Vec<Component>,
+}
+
+impl Wto {
+    /// Orders the blocks in `rpo`, which maps each reachable block to its reverse postorder index.
+    pub fn new(
+        blocks: &IndexVec<BasicBlock, BasicBlockData>,
+        rpo: &HashMap<BasicBlock, usize>,
+    ) -> Self {
+        let mut include = IndexVec::from_elem_n(false, blocks.len());
+        for &block in rpo.keys() {
+            include[block] = true;
+        }
+        Wto {
+            components: decompose(blocks, rpo, &include),
+        }
+    }
+
+    /// Heads of every cycle, at any depth
+    pub fn heads(&self) -> HashSet<BasicBlock> {
+        let mut heads = HashSet::new();
+        let mut stack = self.components.iter().collect::<Vec<_>>();
+        while let Some(component) = stack.pop() {
+            if let Component::Cycle { head, body } = component {
+                heads.insert(*head);
+                stack.extend(body.iter());
+            }
+        }
+        heads
+    }
+}
+
+fn decompose(
+    blocks: &IndexVec<BasicBlock, BasicBlockData>,
+    rpo: &HashMap<BasicBlock, usize>,
+    include: &IndexVec<BasicBlock, bool>,
+) -> Vec<Component> {
+    let components = strongly_connected_components(blocks, include);
+    let count = components
+        .iter()
+        .filter_map(|c| *c)
+        .max()
+        .map_or(0, |c| c + 1);
+    let mut members = vec![Vec::new(); count];
+    for (block, component) in components.iter_enumerated() {
+        if let Some(component) = component {
+            members[*component].push(block);
+        }
+    }
+
+    // Tarjan's algorithm finds the components in reverse topological order
+    let mut result = Vec::with_capacity(count);
+    for members in members.into_iter().rev() {
+        // The first block in reverse postorder is an entry of the component
+        let head = *members.iter().min_by_key(|block| rpo[*block]).unwrap();
+        let is_cycle = members.len() > 1
+            || blocks[head]
+                .terminator()
+                .successors()
+                .any(|&suc| suc == head);
+        if !is_cycle {
+            result.push(Component::Vertex(head));
+            continue;
+        }
+        let mut body = IndexVec::from_elem_n(false, blocks.len());
+        for &block in members.iter() {
+            body[block] = block != head;
+        }
+        result.push(Component::Cycle {
+            head,
+            body: decompose(blocks, rpo, &body),
+        });
+    }
+    result
+}